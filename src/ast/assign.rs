@@ -1,5 +1,5 @@
 use super::{AstNode, Expr, Assign};
-use runtime::{Value, VType, Scope, Signal, FuncMap, hash_value};
+use runtime::{Value, VType, Scope, Signal, FuncMap, hash_value, get_field};
 use error::{Error, Type, RunCode};
 
 pub struct IndexAssign {
@@ -104,7 +104,15 @@ impl Assign for AccessAssign {
         use Value::*;
 
         match var {
-            Obj(ref o) => {
+            // Fields only found in an object's prototypes are given to the object itself.
+            // Writing inside them would change the prototype's value, so that isn't allowed.
+            Obj(ref o, _) if !o.borrow().contains_key(&self.field_name) => {
+                match (get_field(&var, &self.field_name), &self.child_op) {
+                    (Some(_), None) => {o.borrow_mut().insert(self.field_name.clone(), val); Signal::Done},
+                    _ => Signal::Error(Error::new(Type::RunTime(RunCode::FieldNotFound))),
+                }
+            },
+            Obj(ref o, _) | Struct(_, ref o) => {
                 let mut object = o.borrow_mut();
 
                 let field = match object.get_mut(&self.field_name) {
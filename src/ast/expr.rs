@@ -1,6 +1,6 @@
-use super::{Expr, AstNode, FuncRoot};
-use runtime::{Value, VType, Scope, ExprRes, FuncMap, core_func_call, hash_value, equal};
-use error::{mserr, Type, RunCode, Error};
+use super::{Expr, AstNode, FuncRoot, Pattern, Called};
+use runtime::{Value, VType, Scope, ExprRes, FuncMap, core_func_call, hash_value, equal, truthy, get_field, range_get, range_values};
+use error::{mserr, mserr_at, Type, RunCode, Error, Span};
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -33,115 +33,271 @@ pub struct RangeExpr {
     start: Box<dyn Expr>,
     step: Option<Box<dyn Expr>>,
     end: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct IndexExpr {
     base: Box<dyn Expr>,
     index: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct AccessExpr {
     base: Box<dyn Expr>,
     access_id: String,
+    span: Span,
 }
 
 pub struct AddExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct SubExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct MulExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct DivExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct ModExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct NegExpr {
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct EqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct NEqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct TrueEqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct TrueNEqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct GThanExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct GEqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct LThanExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct LEqExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct NotExpr {
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct AndExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct OrExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct XorExpr {
     left: Box<dyn Expr>,
     right: Box<dyn Expr>,
+    span: Span,
+}
+
+pub struct LogicAndExpr {
+    left: Box<dyn Expr>,
+    right: Box<dyn Expr>,
+    span: Span,
+}
+
+pub struct LogicOrExpr {
+    left: Box<dyn Expr>,
+    right: Box<dyn Expr>,
+    span: Span,
+}
+
+// String built from literal text and embedded expressions
+pub struct InterpExpr {
+    parts: Vec<Box<dyn Expr>>,
+    span: Span,
+}
+
+pub struct CondExpr {
+    cond: Box<dyn Expr>,
+    then_expr: Box<dyn Expr>,
+    else_expr: Box<dyn Expr>,
+    span: Span,
+}
+
+pub struct MatchExpr {
+    cond: Box<dyn Expr>,
+    cases: Vec<(Pattern, Box<dyn Expr>)>,
+    otherwise: Option<Box<dyn Expr>>,
+    span: Span,
 }
 
 pub struct FuncCall {
     base: Box<dyn Expr>,
     args: Vec<Box<dyn Expr>>,
+    span: Span,
 }
 
 pub struct CoreFuncCall {
     name: String,
     base: Box<dyn Expr>,
     args: Vec<Box<dyn Expr>>,
+    span: Span,
+}
+
+pub struct StructExpr {
+    package: String,
+    name: String,
+    fields: Box<dyn Expr>,
+    span: Span,
+}
+
+pub struct VariantExpr {
+    package: String,
+    name: String,
+    variant: String,
+    args: Vec<Box<dyn Expr>>,
+    span: Span,
+}
+
+
+// Prints the base of a call followed by its arguments, separated by spaces.
+fn print_call(base: &dyn Expr, args: &[Box<dyn Expr>]) -> String {
+    let mut out = base.print();
+    for a in args {
+        out.push(' ');
+        out.push_str(&a.print());
+    }
+    out
+}
+
+
+// Calls a function value. Methods get the object they were called on as `self`.
+pub fn call_value(func: Value, this: Option<Value>, args: &[Value], f: &FuncMap) -> ExprRes {
+    match (func, this) {
+        (Value::Func(package, name), Some(this)) => f.call_method(&package.borrow(), &name.borrow(), &this, args),
+        (Value::Func(package, name), None) => f.call_fn(&package.borrow(), &name.borrow(), args),
+        (Value::Closure(func, captures), this) => func.borrow().call(args, f, Some(&captures.borrow()), this.as_ref()),
+        _ => mserr(Type::RunTime(RunCode::InvalidCall)),
+    }
+}
+
+// Calls a function value so that a yield inside it suspends the call rather than failing.
+pub fn call_suspendable(func: Value, this: Option<Value>, args: &[Value], f: &FuncMap) -> Result<Called, Error> {
+    match (func, this) {
+        (Value::Func(package, name), this) => f.call_suspendable(&package.borrow(), &name.borrow(), this.as_ref(), args),
+        (Value::Closure(func, captures), this) => func.borrow().call_suspendable(args, f, Some(&captures.borrow()), this.as_ref()),
+        _ => Err(Error::new(Type::RunTime(RunCode::InvalidCall))),
+    }
+}
+
+// Calls an operator hook method like `__add`, if the value is an object or struct that has it.
+pub fn call_hook(val: &Value, hook: &str, args: &[Value], f: &FuncMap) -> Option<ExprRes> {
+    match *val {
+        Value::Obj(_,_) | Value::Struct(_,_) => get_field(val, hook).map(|h| call_value(h, Some(val.clone()), args, f)),
+        _ => None,
+    }
+}
+
+// Operator hook of the left operand, called with the right.
+fn binary_hook(left: &Value, hook: &str, right: &Value, f: &FuncMap, span: &Span) -> Option<ExprRes> {
+    call_hook(left, hook, std::slice::from_ref(right), f).map(|res| res.map_err(|e| e.at(span)))
+}
+
+// Whether a is less than b, comparing numbers or calling the `__lt` hook of a. Also used to sort.
+pub fn less_than(a: &Value, b: &Value, f: &FuncMap) -> Result<bool, Error> {
+    use Value::*;
+    use self::VType::*;
+
+    if let Some(res) = call_hook(a, "__lt", std::slice::from_ref(b), f) {
+        return truthy(&res?).ok_or_else(|| Error::new(Type::RunTime(RunCode::TypeError)));
+    }
+
+    match (a, b) {
+        (Val(I(x)), Val(I(y))) => Ok(x < y),
+        (Val(I(x)), Val(F(y))) => Ok((*x as f64) < *y),
+        (Val(F(x)), Val(I(y))) => Ok(*x < (*y as f64)),
+        (Val(F(x)), Val(F(y))) => Ok(x < y),
+        _ => Err(Error::new(Type::RunTime(RunCode::TypeError))),
+    }
+}
+
+// Whether a is less than or equal to b, for `>` and `<=` when only a has a `__lt` hook. This is
+// `a < b`, or else `a == b` through its `__eq` hook or by comparing fields.
+fn left_lt_or_eq(a: &Value, b: &Value, f: &FuncMap, span: &Span) -> Option<Result<bool, Error>> {
+    let lt = binary_hook(a, "__lt", b, f, span)?;
+    let type_error = || Error::new(Type::RunTime(RunCode::TypeError)).at(span);
+
+    Some(lt.and_then(|lt| match truthy(&lt) {
+        Some(true) => Ok(true),
+        Some(false) => match binary_hook(a, "__eq", b, f, span) {
+            Some(eq) => truthy(&eq?).ok_or_else(type_error),
+            None => Ok(equal(a, b) == Some(true)),
+        },
+        None => Err(type_error()),
+    }))
+}
+
+// Inverts the result of a comparison hook, for the operators derived from it.
+fn negate(res: ExprRes, span: &Span) -> ExprRes {
+    match truthy(&res?) {
+        Some(b) => Ok(Value::Val(VType::B(!b))),
+        None => mserr_at(Type::RunTime(RunCode::TypeError), span),
+    }
 }
 
 
@@ -149,7 +305,27 @@ pub struct CoreFuncCall {
 
 impl AstNode for ValExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        use self::ValExpr::*;
+        match *self {
+            QualId(_, ref n) => n.clone(),
+            Ref(ref n) => format!("ref {}", n),
+            Int(ref v) => v.to_string(),
+            Float(ref v) => format!("{:?}", v),
+            Bool(ref v) => v.to_string(),
+            Pair(ref l, ref r) => format!("<{}, {}>", l.print(), r.print()),
+            Text(ref v) => format!("\"{}\"", v),
+            List(ref l) => format!("[{}]", l.iter().map(|e| e.print()).collect::<Vec<_>>().join(", ")),
+            Obj(ref o) => {
+                let fields = o.iter().map(|(n, e)| format!("{}: {}", n, e.print())).collect::<Vec<_>>();
+                format!("{{{}}}", fields.join(", "))
+            },
+            Map(ref m) => {
+                let items = m.iter().map(|(k, v)| format!("[{}] = {}", k.print(), v.print())).collect::<Vec<_>>();
+                format!("{{{}}}", items.join(", "))
+            },
+            Closure(_) => "func".to_string(),
+            Null => "null".to_string(),
+        }
     }
 }
 
@@ -197,7 +373,7 @@ impl Expr for ValExpr {
                     let el = expr.eval(state, f)?;
                     r.borrow_mut().insert(n.clone(), el);
                 }
-                Ok(Value::Obj(r))
+                Ok(Value::Obj(r, Rc::new(RefCell::new(None))))
             },
             Map(ref m) => {
                 let r = Rc::new(RefCell::new(HashMap::new()));
@@ -220,18 +396,22 @@ impl Expr for ValExpr {
 
 
 impl RangeExpr {
-    pub fn new(s: Box<dyn Expr>, step: Option<Box<dyn Expr>>, e: Box<dyn Expr>) -> Self {
+    pub fn new(s: Box<dyn Expr>, step: Option<Box<dyn Expr>>, e: Box<dyn Expr>, sp: Span) -> Self {
         RangeExpr {
             start: s,
             step: step,
             end: e,
+            span: sp,
         }
     }
 }
 
 impl AstNode for RangeExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        match self.step {
+            Some(ref st) => format!("[{}..{}..{}]", self.start.print(), st.print(), self.end.print()),
+            None => format!("[{}..{}]", self.start.print(), self.end.print()),
+        }
     }
 }
 
@@ -242,48 +422,46 @@ impl Expr for RangeExpr {
 
         let start = self.start.eval(state, f)?;
         let end = self.end.eval(state, f)?;
-        let step = self.step.as_ref().map_or(Ok(1), |s| match s.eval(state, f)? {
+        let step = self.step.as_ref().map(|s| match s.eval(state, f)? {
             Val(I(i)) => Ok(i),
             Ref(ref r) => match *r.borrow() {
                 I(i) => Ok(i),
-                _ => Err(Error::new(Type::RunTime(RunCode::TypeError))),
+                _ => Err(Error::new(Type::RunTime(RunCode::TypeError)).at(&self.span)),
             },
-            _ => Err(Error::new(Type::RunTime(RunCode::TypeError))),
-        })?;
+            _ => Err(Error::new(Type::RunTime(RunCode::TypeError)).at(&self.span)),
+        }).transpose()?;
 
-        let (mut start_num, end_num) = match (start,end) {
+        let (start_num, end_num) = match (start,end) {
             (Val(I(s)), Val(I(e))) => (s,e),
-            _ => return mserr(Type::RunTime(RunCode::TypeError)),
+            _ => return mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         };
 
-        if start_num >= end_num {
-            return mserr(Type::RunTime(RunCode::InvalidRange));
-        }
-
-        let r = Rc::new(RefCell::new(Vec::new()));
-
-        while start_num < end_num {
-            r.borrow_mut().push(Val(I(start_num)));
-            start_num += step;
-        }
+        // Without a step, ranges count down if the end is lower than the start.
+        let step = match step {
+            Some(0) => return mserr_at(Type::RunTime(RunCode::InvalidRange), &self.span),
+            Some(s) => s,
+            None if start_num > end_num => -1,
+            None => 1,
+        };
 
-        Ok(Value::List(r))
+        Ok(Value::Range(start_num, end_num, step))
     }
 }
 
 
 impl IndexExpr {
-    pub fn new(b: Box<dyn Expr>, i: Box<dyn Expr>) -> Self {
+    pub fn new(b: Box<dyn Expr>, i: Box<dyn Expr>, s: Span) -> Self {
         IndexExpr {
             base: b,
             index: i,
+            span: s,
         }
     }
 }
 
 impl AstNode for IndexExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(index {} {})", self.base.print(), self.index.print())
     }
 }
 
@@ -294,6 +472,10 @@ impl Expr for IndexExpr {
         let l = self.base.eval(state, f)?;
         let i = self.index.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&l, "__index", &i, f, &self.span) {
+            return res;
+        }
+
         match (l,i) {
             (List(l),Val(I(i))) => {
                 let list = l.borrow();
@@ -303,12 +485,16 @@ impl Expr for IndexExpr {
                 } else if (i < 0) && ((i.abs() as usize) <= list.len()) {
                     ((list.len() as i64) + i) as usize
                 } else {
-                    return mserr(Type::RunTime(RunCode::OutOfBounds));
+                    return mserr_at(Type::RunTime(RunCode::OutOfBounds), &self.span);
                 };
 
                 Ok(list[index].clone())
             },
-            (List(_),_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (List(_),_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+            (Range(start, end, step),Val(I(i))) => match range_get(start, end, step, i) {
+                Some(n) => Ok(Val(I(n))),
+                None => mserr_at(Type::RunTime(RunCode::OutOfBounds), &self.span),
+            },
             /*(Str(s),Int(i)) => {
                 let text = s.borrow();
                 /*if (i >= 0) && ((i as usize) < text.len()) {
@@ -321,65 +507,76 @@ impl Expr for IndexExpr {
             },*/
             (Map(m),iv) => {
                 let map = m.borrow();
-                let index = hash_value(&iv)?;
+                let index = hash_value(&iv).map_err(|e| e.at(&self.span))?;
                 let (_key, val) = match map.get(&index) {
                     Some(v) => v,
-                    None    => return mserr(Type::RunTime(RunCode::OutOfBounds)),
+                    None    => return mserr_at(Type::RunTime(RunCode::OutOfBounds), &self.span),
                 };
                 Ok(val.clone())
             },
-            _ => mserr(Type::RunTime(RunCode::TypeError)),
+            _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl AccessExpr {
-    pub fn new(b: Box<dyn Expr>, a: &str) -> Self {
+    pub fn new(b: Box<dyn Expr>, a: &str, s: Span) -> Self {
         AccessExpr {
             base: b,
             access_id: a.to_string(),
+            span: s,
+        }
+    }
+
+    // Gets the accessed field of an object, which may come from its prototypes.
+    fn field(&self, o: &Value) -> ExprRes {
+        use Value::*;
+
+        match *o {
+            Obj(_,_) | Struct(_,_) => match get_field(o, &self.access_id) {
+                Some(v) => Ok(v),
+                None => mserr_at(Type::RunTime(RunCode::FieldNotFound), &self.span),
+            },
+            _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 impl AstNode for AccessExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(. {} {})", self.base.print(), self.access_id)
     }
 }
 
 impl Expr for AccessExpr {
     fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
-        use Value::*;
         let o = self.base.eval(state, f)?;
+        self.field(&o)
+    }
 
-        match o {
-            Obj(o) => {
-                let obj = o.borrow();
-                match obj.get(&self.access_id) {
-                    Some(v) => Ok(v.clone()),
-                    None => mserr(Type::RunTime(RunCode::FieldNotFound)),
-                }
-            },
-            _ => mserr(Type::RunTime(RunCode::TypeError)),
-        }
+    // Fields called as methods get the object they were accessed on as `self`.
+    fn eval_callee(&self, state: &mut Scope, f: &FuncMap) -> Result<(Value, Option<Value>), Error> {
+        let o = self.base.eval(state, f)?;
+        let field = self.field(&o)?;
+        Ok((field, Some(o)))
     }
 }
 
 
 impl AddExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         AddExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for AddExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(+ {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -390,6 +587,10 @@ impl Expr for AddExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__add", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x + y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(F(x as f64 + y))),
@@ -410,24 +611,37 @@ impl Expr for AddExpr {
                 let list = Rc::new(RefCell::new([&x[..], &y[..]].concat()));
                 Ok(List(list))
             },
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (List(x),Range(a,b,c)) => Ok(List(Rc::new(RefCell::new([&x.borrow()[..], &range_values(a, b, c)[..]].concat())))),
+            (Range(a,b,c),List(y)) => Ok(List(Rc::new(RefCell::new([&range_values(a, b, c)[..], &y.borrow()[..]].concat())))),
+            (Str(x),y) => match call_hook(&y, "__str", &[], f) {
+                Some(Ok(Str(y))) => Ok(refstr!(x.borrow().clone() + &*y.borrow())),
+                Some(Err(e)) => Err(e.at(&self.span)),
+                _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+            },
+            (x,Str(y)) => match call_hook(&x, "__str", &[], f) {
+                Some(Ok(Str(x))) => Ok(refstr!(x.borrow().clone() + &*y.borrow())),
+                Some(Err(e)) => Err(e.at(&self.span)),
+                _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+            },
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl SubExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         SubExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for SubExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(- {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -438,29 +652,34 @@ impl Expr for SubExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__sub", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x - y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(F(x as f64 - y))),
             (Val(F(x)),Val(I(y))) => Ok(Val(F(x - y as f64))),
             (Val(F(x)),Val(F(y))) => Ok(Val(F(x - y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl MulExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         MulExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for MulExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(* {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -471,6 +690,10 @@ impl Expr for MulExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__mul", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x * y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(F(x as f64 * y))),
@@ -479,7 +702,7 @@ impl Expr for MulExpr {
             (Str(x),Val(I(y))) => Ok(refstr!(x.borrow().repeat(y as usize))),
             (List(x),Val(I(y))) => {
                 if y < 0 {
-                    mserr(Type::RunTime(RunCode::InvalidNegative)) // Negative value?
+                    mserr_at(Type::RunTime(RunCode::InvalidNegative), &self.span) // Negative value?
                 } else {
                     let x = x.borrow();
                     let list = Rc::new(RefCell::new(Vec::new()));
@@ -489,24 +712,25 @@ impl Expr for MulExpr {
                     Ok(List(list))
                 }
             },
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl DivExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         DivExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for DivExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(/ {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -517,31 +741,36 @@ impl Expr for DivExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__div", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
-            (_,Val(I(0))) => mserr(Type::RunTime(RunCode::DivideByZero)),
+            (_,Val(I(0))) => mserr_at(Type::RunTime(RunCode::DivideByZero), &self.span),
             //(_,Val(F(0.0))) => mserr(Type::RunTime(RunCode::DivideByZero)), TODO: sort this
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x / y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(F(x as f64 / y))),
             (Val(F(x)),Val(I(y))) => Ok(Val(F(x / y as f64))),
             (Val(F(x)),Val(F(y))) => Ok(Val(F(x / y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl ModExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         ModExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for ModExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(% {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -552,25 +781,30 @@ impl Expr for ModExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__mod", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x % y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl NegExpr {
-    pub fn new(r: Box<dyn Expr>) -> Self {
+    pub fn new(r: Box<dyn Expr>, s: Span) -> Self {
         NegExpr {
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for NegExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(- {})", self.right.print())
     }
 }
 
@@ -580,27 +814,32 @@ impl Expr for NegExpr {
         use self::VType::*;
         let a = self.right.eval(state, f)?;
 
+        if let Some(res) = call_hook(&a, "__neg", &[], f) {
+            return res.map_err(|e| e.at(&self.span));
+        }
+
         match a {
             Val(I(x)) => Ok(Val(I(-x))),
             Val(F(x)) => Ok(Val(F(-x))),
-            _ => mserr(Type::RunTime(RunCode::TypeError)),
+            _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl EqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         EqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for EqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(== {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -611,6 +850,10 @@ impl Expr for EqExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__eq", &b, f, &self.span) {
+            return res;
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(B(x == y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(B(x == (y as i64)))),
@@ -645,7 +888,7 @@ impl Expr for EqExpr {
                 }
                 Ok(Val(B(true)))
             },
-            (Obj(x),Obj(y)) => {
+            (Obj(x,_),Obj(y,_)) => {
                 if x.borrow().len() != y.borrow().len() {
                     return Ok(Val(B(false)));
                 }
@@ -656,6 +899,11 @@ impl Expr for EqExpr {
                 }
                 Ok(Val(B(true)))
             },
+            (x @ Pair(_,_),y @ Pair(_,_)) => Ok(Val(B(equal(&x, &y) == Some(true)))),
+            (x @ Struct(_,_),y @ Struct(_,_)) => Ok(Val(B(equal(&x, &y) == Some(true)))),
+            (x @ Enum(_,_),y @ Enum(_,_)) => Ok(Val(B(equal(&x, &y) == Some(true)))),
+            (x @ Range(_,_,_),y @ Range(_,_,_)) => Ok(Val(B(equal(&x, &y) == Some(true)))),
+            (x @ Range(_,_,_),y @ List(_)) | (x @ List(_),y @ Range(_,_,_)) => Ok(Val(B(equal(&x, &y) == Some(true)))),
             (Null,Null) => Ok(Val(B(true))),
             (_,_) => Ok(Val(B(false))),
             //(_,_) => mserr(Type::RunTime(RunCode::TypeError)),
@@ -665,17 +913,18 @@ impl Expr for EqExpr {
 
 
 impl NEqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         NEqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for NEqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(!= {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -686,6 +935,10 @@ impl Expr for NEqExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__eq", &b, f, &self.span) {
+            return negate(res, &self.span);
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(B(x != y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(B(x != (y as i64)))),
@@ -720,7 +973,7 @@ impl Expr for NEqExpr {
                 }
                 Ok(Val(B(false)))
             },
-            (Obj(x),Obj(y)) => {
+            (Obj(x,_),Obj(y,_)) => {
                 if x.borrow().len() != y.borrow().len() {
                     return Ok(Val(B(true)));
                 }
@@ -731,6 +984,11 @@ impl Expr for NEqExpr {
                 }
                 Ok(Val(B(false)))
             },
+            (x @ Pair(_,_),y @ Pair(_,_)) => Ok(Val(B(equal(&x, &y) != Some(true)))),
+            (x @ Struct(_,_),y @ Struct(_,_)) => Ok(Val(B(equal(&x, &y) != Some(true)))),
+            (x @ Enum(_,_),y @ Enum(_,_)) => Ok(Val(B(equal(&x, &y) != Some(true)))),
+            (x @ Range(_,_,_),y @ Range(_,_,_)) => Ok(Val(B(equal(&x, &y) != Some(true)))),
+            (x @ Range(_,_,_),y @ List(_)) | (x @ List(_),y @ Range(_,_,_)) => Ok(Val(B(equal(&x, &y) != Some(true)))),
             (Null,Null) => Ok(Val(B(false))),
             (_,_) => Ok(Val(B(true))),
             //(_,_) => mserr(Type::RunTime(RunCode::TypeError)),
@@ -740,17 +998,18 @@ impl Expr for NEqExpr {
 
 
 impl TrueEqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         TrueEqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for TrueEqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(=== {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -761,24 +1020,25 @@ impl Expr for TrueEqExpr {
 
         match equal(&a, &b) {
             Some(res) => Ok(Value::Val(VType::B(res))),
-            None      => mserr(Type::RunTime(RunCode::TypeError)),
+            None      => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl TrueNEqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         TrueNEqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for TrueNEqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(!== {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -789,24 +1049,25 @@ impl Expr for TrueNEqExpr {
 
         match equal(&a, &b) {
             Some(res) => Ok(Value::Val(VType::B(!res))),
-            None      => mserr(Type::RunTime(RunCode::TypeError)),
+            None      => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl GThanExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         GThanExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for GThanExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(> {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -817,29 +1078,37 @@ impl Expr for GThanExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&b, "__lt", &a, f, &self.span) {
+            return res;
+        }
+        if let Some(res) = left_lt_or_eq(&a, &b, f, &self.span) {
+            return res.map(|x| Val(B(!x)));
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(B(x > y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(B((x as f64) > y))),
             (Val(F(x)),Val(I(y))) => Ok(Val(B(x > (y as f64)))),
             (Val(F(x)),Val(F(y))) => Ok(Val(B(x > y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl GEqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         GEqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for GEqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(>= {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -850,29 +1119,34 @@ impl Expr for GEqExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&a, "__lt", &b, f, &self.span) {
+            return negate(res, &self.span);
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(B(x >= y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(B((x as f64) >= y))),
             (Val(F(x)),Val(I(y))) => Ok(Val(B(x >= (y as f64)))),
             (Val(F(x)),Val(F(y))) => Ok(Val(B(x >= y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl LThanExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         LThanExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for LThanExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(< {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -883,29 +1157,24 @@ impl Expr for LThanExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
-        match (a,b) {
-            (Val(I(x)),Val(I(y))) => Ok(Val(B(x < y))),
-            (Val(I(x)),Val(F(y))) => Ok(Val(B((x as f64) < y))),
-            (Val(F(x)),Val(I(y))) => Ok(Val(B(x < (y as f64)))),
-            (Val(F(x)),Val(F(y))) => Ok(Val(B(x < y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
-        }
+        less_than(&a, &b, f).map(|x| Val(B(x))).map_err(|e| e.at(&self.span))
     }
 }
 
 
 impl LEqExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         LEqExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for LEqExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(<= {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -916,28 +1185,36 @@ impl Expr for LEqExpr {
         let a = self.left.eval(state, f)?;
         let b = self.right.eval(state, f)?;
 
+        if let Some(res) = binary_hook(&b, "__lt", &a, f, &self.span) {
+            return negate(res, &self.span);
+        }
+        if let Some(res) = left_lt_or_eq(&a, &b, f, &self.span) {
+            return res.map(|x| Val(B(x)));
+        }
+
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(B(x <= y))),
             (Val(I(x)),Val(F(y))) => Ok(Val(B((x as f64) <= y))),
             (Val(F(x)),Val(I(y))) => Ok(Val(B(x <= (y as f64)))),
             (Val(F(x)),Val(F(y))) => Ok(Val(B(x <= y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl NotExpr {
-    pub fn new(e: Box<dyn Expr>) -> Self {
+    pub fn new(e: Box<dyn Expr>, s: Span) -> Self {
         NotExpr {
             right: e,
+            span: s,
         }
     }
 }
 
 impl AstNode for NotExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(! {})", self.right.print())
     }
 }
 
@@ -950,24 +1227,25 @@ impl Expr for NotExpr {
         match a {
             Val(I(x)) => Ok(Val(I(!x))),
             Val(B(x)) => Ok(Val(B(!x))),
-            _ => mserr(Type::RunTime(RunCode::TypeError)),
+            _ => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl AndExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         AndExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for AndExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(& {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -981,24 +1259,25 @@ impl Expr for AndExpr {
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x & y))),
             (Val(B(x)),Val(B(y))) => Ok(Val(B(x && y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl OrExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         OrExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for OrExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(| {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -1012,24 +1291,25 @@ impl Expr for OrExpr {
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x | y))),
             (Val(B(x)),Val(B(y))) => Ok(Val(B(x || y))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
         }
     }
 }
 
 
 impl XorExpr {
-    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>) -> Self {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
         XorExpr {
             left: l,
             right: r,
+            span: s,
         }
     }
 }
 
 impl AstNode for XorExpr {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(^ {} {})", self.left.print(), self.right.print())
     }
 }
 
@@ -1043,30 +1323,206 @@ impl Expr for XorExpr {
         match (a,b) {
             (Val(I(x)),Val(I(y))) => Ok(Val(I(x ^ y))),
             (Val(B(x)),Val(B(y))) => Ok(Val(B(if x == y {false} else {true}))),
-            (_,_) => mserr(Type::RunTime(RunCode::TypeError)),
+            (_,_) => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+    }
+}
+
+
+impl LogicAndExpr {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
+        LogicAndExpr {
+            left: l,
+            right: r,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for LogicAndExpr {
+    fn print(&self) -> String {
+        format!("(&& {} {})", self.left.print(), self.right.print())
+    }
+}
+
+impl Expr for LogicAndExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        // Right side is only evaluated if the left side is true.
+        match truthy(&self.left.eval(state, f)?) {
+            Some(true) => {},
+            Some(false) => return Ok(Value::Val(VType::B(false))),
+            None => return mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+
+        match truthy(&self.right.eval(state, f)?) {
+            Some(b) => Ok(Value::Val(VType::B(b))),
+            None => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+    }
+}
+
+
+impl LogicOrExpr {
+    pub fn new(l: Box<dyn Expr>, r: Box<dyn Expr>, s: Span) -> Self {
+        LogicOrExpr {
+            left: l,
+            right: r,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for LogicOrExpr {
+    fn print(&self) -> String {
+        format!("(|| {} {})", self.left.print(), self.right.print())
+    }
+}
+
+impl Expr for LogicOrExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        // Right side is only evaluated if the left side is false.
+        match truthy(&self.left.eval(state, f)?) {
+            Some(true) => return Ok(Value::Val(VType::B(true))),
+            Some(false) => {},
+            None => return mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+
+        match truthy(&self.right.eval(state, f)?) {
+            Some(b) => Ok(Value::Val(VType::B(b))),
+            None => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+    }
+}
+
+
+impl InterpExpr {
+    pub fn new(p: Vec<Box<dyn Expr>>, s: Span) -> Self {
+        InterpExpr {
+            parts: p,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for InterpExpr {
+    fn print(&self) -> String {
+        let parts = self.parts.iter().map(|p| p.print()).collect::<Vec<_>>();
+        format!("(str {})", parts.join(" "))
+    }
+}
+
+impl Expr for InterpExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        let mut text = String::new();
+
+        for p in &self.parts {
+            let val = p.eval(state, f)?;
+
+            match core_func_call("to_string", val, &[], f).map_err(|e| e.at(&self.span))? {
+                Value::Str(s) => text.push_str(&s.borrow()),
+                _ => return mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+            }
+        }
+
+        Ok(refstr!(text))
+    }
+}
+
+
+impl CondExpr {
+    pub fn new(c: Box<dyn Expr>, t: Box<dyn Expr>, e: Box<dyn Expr>, s: Span) -> Self {
+        CondExpr {
+            cond: c,
+            then_expr: t,
+            else_expr: e,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for CondExpr {
+    fn print(&self) -> String {
+        format!("(? {} {} {})", self.cond.print(), self.then_expr.print(), self.else_expr.print())
+    }
+}
+
+impl Expr for CondExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        let c = self.cond.eval(state, f)?;
+
+        match truthy(&c) {
+            Some(true) => self.then_expr.eval(state, f),
+            Some(false) => self.else_expr.eval(state, f),
+            None => mserr_at(Type::RunTime(RunCode::TypeError), &self.span),
+        }
+    }
+}
+
+
+impl MatchExpr {
+    pub fn new(m: Box<dyn Expr>, c: Vec<(Pattern, Box<dyn Expr>)>, o: Option<Box<dyn Expr>>, s: Span) -> Self {
+        MatchExpr {
+            cond: m,
+            cases: c,
+            otherwise: o,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for MatchExpr {
+    fn print(&self) -> String {
+        let mut out = format!("(match {}", self.cond.print());
+        for (case, expr) in self.cases.iter() {
+            out.push_str(&format!(" ({} {})", case.print(), expr.print()));
+        }
+        if let Some(ref e) = self.otherwise {
+            out.push_str(&format!(" (else {})", e.print()));
+        }
+        out.push(')');
+        out
+    }
+}
+
+impl Expr for MatchExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        let c = self.cond.eval(state, f)?;
+
+        for (case, expr) in self.cases.iter() {
+            if case.matches(&c, state, f).map_err(|e| e.at(&self.span))? {
+                let res = expr.eval(state, f);
+                state.reduce();
+                return res;
+            }
+        }
+
+        match self.otherwise {
+            Some(ref e) => e.eval(state, f),
+            None => Ok(Value::Null),
         }
     }
 }
 
 
 impl FuncCall {
-    pub fn new(b: Box<dyn Expr>, a: Vec<Box<dyn Expr>>) -> Self {
+    pub fn new(b: Box<dyn Expr>, a: Vec<Box<dyn Expr>>, s: Span) -> Self {
         FuncCall {
             base: b,
             args: a,
+            span: s,
         }
     }
 }
 
 impl AstNode for FuncCall {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(call {})", print_call(&*self.base, &self.args))
     }
 }
 
 impl Expr for FuncCall {
     fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
-        let base = self.base.eval(state, f)?;
+        let (base, this) = self.base.eval_callee(state, f)?;
 
         let mut func_args = Vec::new();
 
@@ -1077,28 +1533,37 @@ impl Expr for FuncCall {
             }
         }
 
-        match base {
-            Value::Func(package, name) => f.call_fn(&package.borrow(), &name.borrow(), &func_args),
-            Value::Closure(func, captures) => func.borrow().call(&func_args, f, Some(&captures.borrow())),
-            _ => mserr(Type::RunTime(RunCode::InvalidCall)),
+        call_value(base, this, &func_args, f).map_err(|e| e.at(&self.span))
+    }
+
+    fn eval_call(&self, state: &mut Scope, f: &FuncMap) -> Result<Called, Error> {
+        let (base, this) = self.base.eval_callee(state, f)?;
+
+        let mut func_args = Vec::new();
+
+        for a in &self.args {
+            func_args.push(a.eval(state, f)?);
         }
+
+        call_suspendable(base, this, &func_args, f).map_err(|e| e.at(&self.span))
     }
 }
 
 
 impl CoreFuncCall {
-    pub fn new(n: &str, b: Box<dyn Expr>, a: Vec<Box<dyn Expr>>) -> Self {
+    pub fn new(n: &str, b: Box<dyn Expr>, a: Vec<Box<dyn Expr>>, s: Span) -> Self {
         CoreFuncCall {
             name: n.to_string(),
             base: b,
             args: a,
+            span: s,
         }
     }
 }
 
 impl AstNode for CoreFuncCall {
     fn print(&self) -> String {
-        "Val".to_string()
+        format!("(->{} {})", self.name, print_call(&*self.base, &self.args))
     }
 }
 
@@ -1115,7 +1580,65 @@ impl Expr for CoreFuncCall {
             }
         }
 
-        core_func_call(&self.name, base, &func_args)
+        core_func_call(&self.name, base, &func_args, f).map_err(|e| e.at(&self.span))
+    }
+}
+
+
+impl StructExpr {
+    pub fn new(p: String, n: &str, fields: Box<dyn Expr>, s: Span) -> Self {
+        StructExpr {
+            package: p,
+            name: n.to_string(),
+            fields,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for StructExpr {
+    fn print(&self) -> String {
+        format!("{}{}", self.name, self.fields.print())
+    }
+}
+
+impl Expr for StructExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        let fields = self.fields.eval(state, f)?;
+
+        f.construct_struct(&self.package, &self.name, &fields).map_err(|e| e.at(&self.span))
+    }
+}
+
+
+impl VariantExpr {
+    pub fn new(p: String, n: &str, v: &str, a: Vec<Box<dyn Expr>>, s: Span) -> Self {
+        VariantExpr {
+            package: p,
+            name: n.to_string(),
+            variant: v.to_string(),
+            args: a,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for VariantExpr {
+    fn print(&self) -> String {
+        let args = self.args.iter().map(|a| a.print()).collect::<Vec<_>>();
+        format!("{}::{}({})", self.name, self.variant, args.join(", "))
+    }
+}
+
+impl Expr for VariantExpr {
+    fn eval(&self, state: &mut Scope, f: &FuncMap) -> ExprRes {
+        let mut args = Vec::new();
+
+        for a in &self.args {
+            args.push(a.eval(state, f)?);
+        }
+
+        f.construct_variant(&self.package, &self.name, &self.variant, &args).map_err(|e| e.at(&self.span))
     }
 }
 
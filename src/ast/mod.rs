@@ -2,14 +2,17 @@
 mod stat;
 mod expr;
 mod assign;
+mod pattern;
 mod root;
 
 pub use self::stat::*;
 pub use self::expr::*;
 pub use self::assign::*;
+pub use self::pattern::*;
 pub use self::root::*;
 
 use runtime::{Value, Scope, ExprRes, Signal, FuncMap};
+use error::Error;
 
 pub trait AstNode {
     fn print(&self) -> String;
@@ -18,10 +21,26 @@ pub trait AstNode {
 
 pub trait Expr: AstNode {
     fn eval(&self, &mut Scope, &FuncMap) -> ExprRes;
+
+    // Evaluates the base of a call, along with the object it was accessed on, if any.
+    fn eval_callee(&self, state: &mut Scope, f: &FuncMap) -> Result<(Value, Option<Value>), Error> {
+        Ok((self.eval(state, f)?, None))
+    }
+
+    // Evaluates the expression making up a statement. A call here can be suspended by a yield
+    // inside it, to be finished when the statement is resumed.
+    fn eval_call(&self, state: &mut Scope, f: &FuncMap) -> Result<Called, Error> {
+        self.eval(state, f).map(Called::Returned)
+    }
 }
 
 pub trait Statement: AstNode {
     fn run(&self, &mut Scope, &FuncMap) -> Signal;
+
+    // Whether the statement contains a `yield`, which makes the function it is in a generator.
+    fn can_yield(&self) -> bool {
+        false
+    }
 }
 
 pub trait Assign: AstNode {
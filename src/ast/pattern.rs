@@ -0,0 +1,349 @@
+use super::{AstNode, Expr};
+use runtime::{Value, VType, Scope, Signal, FuncMap, equal, truthy, type_name, get_field, range_iter, range_len};
+use error::{Error, Type, RunCode};
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cmp;
+
+pub enum Pattern {
+    // Matches anything, without binding
+    Any,
+    // Matches anything, and binds it to the name
+    Var(String),
+    // Matches values equal to the expression
+    Value(Box<dyn Expr>),
+    // Matches numbers between the two expressions, inclusive
+    Range(Box<dyn Expr>, Box<dyn Expr>),
+    // Matches lists element by element, with an optional pattern for the remaining elements
+    List(Vec<Pattern>, Option<Box<Pattern>>),
+    // Matches objects that have all of the fields
+    Obj(Vec<(String, Pattern)>),
+    Pair(Box<Pattern>, Box<Pattern>),
+    // Matches instances of an enum variant, given by package, enum and variant name.
+    // Without a list of payload patterns, the payload isn't checked.
+    Enum(String, String, String, Option<Vec<Pattern>>),
+    // Matches values of the named type, with an optional binding
+    Type(Option<String>, String),
+    // Matches if the pattern does, and the condition is true with the pattern's names bound
+    Guard(Box<Pattern>, Box<dyn Expr>),
+}
+
+
+impl Pattern {
+    // Checks if the pattern matches every value.
+    pub fn is_catch_all(&self) -> bool {
+        matches!(*self, Pattern::Any | Pattern::Var(_))
+    }
+
+    // Checks if the pattern matches the value. If it does, a scope level is added holding
+    // any names the pattern binds, which must be removed after running the case.
+    pub fn matches(&self, val: &Value, state: &mut Scope, f: &FuncMap) -> Result<bool, Error> {
+        let mut binds = Vec::new();
+
+        if !self.bind(val, state, f, &mut binds)? {
+            return Ok(false);
+        }
+
+        state.extend();
+        match declare_all(binds, state) {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                state.reduce();
+                Err(e)
+            },
+        }
+    }
+
+    // Declares the names bound by a `var` pattern in the current scope.
+    pub fn declare(&self, val: &Value, state: &mut Scope, f: &FuncMap) -> Result<(), Error> {
+        let mut binds = Vec::new();
+        self.destructure(val, state, f, &mut binds)?;
+        declare_all(binds, state)
+    }
+
+    // Assigns to the existing variables named by a pattern.
+    pub fn assign(&self, val: &Value, state: &mut Scope, f: &FuncMap) -> Result<(), Error> {
+        let mut binds = Vec::new();
+        self.destructure(val, state, f, &mut binds)?;
+
+        for (n, v) in binds {
+            if let Signal::Error(e) = state.set_var(&n, v) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    // Collects the names bound by a declaration or assignment pattern.
+    // Unlike in a match, a value that doesn't fit the pattern is an error.
+    fn destructure(&self, val: &Value, state: &mut Scope, f: &FuncMap, binds: &mut Vec<(String, Value)>) -> Result<(), Error> {
+        match *self {
+            Pattern::List(ref items, ref rest) => {
+                let (len, list, rest_val) = match split_list(val, items.len()) {
+                    Some(l) => l,
+                    None => return Err(type_error()),
+                };
+
+                if len < items.len() {
+                    return Err(Error::new(Type::RunTime(RunCode::MissingElement)));
+                }
+                if rest.is_none() && len > items.len() {
+                    return Err(Error::new(Type::RunTime(RunCode::TooManyElements)));
+                }
+
+                for (p, v) in items.iter().zip(list.iter()) {
+                    p.destructure(v, state, f, binds)?;
+                }
+
+                match *rest {
+                    Some(ref p) => p.destructure(&rest_val, state, f, binds),
+                    None => Ok(()),
+                }
+            },
+            Pattern::Obj(ref fields) => {
+                match *val {
+                    Value::Obj(_,_) | Value::Struct(_,_) => {},
+                    _ => return Err(type_error()),
+                }
+
+                for (name, p) in fields.iter() {
+                    let field = match get_field(val, name) {
+                        Some(v) => v,
+                        None => return Err(Error::new(Type::RunTime(RunCode::FieldNotFound))),
+                    };
+
+                    p.destructure(&field, state, f, binds)?;
+                }
+                Ok(())
+            },
+            Pattern::Pair(ref a, ref b) => {
+                let (x, y) = match *val {
+                    Value::Pair(ref x, ref y) => (x.borrow().clone(), y.borrow().clone()),
+                    _ => return Err(type_error()),
+                };
+
+                a.destructure(&x, state, f, binds)?;
+                b.destructure(&y, state, f, binds)
+            },
+            ref p => match p.bind(val, state, f, binds)? {
+                true => Ok(()),
+                false => Err(type_error()),
+            },
+        }
+    }
+
+    // Checks if the pattern matches the value, collecting the names it binds.
+    fn bind(&self, val: &Value, state: &mut Scope, f: &FuncMap, binds: &mut Vec<(String, Value)>) -> Result<bool, Error> {
+        match *self {
+            Pattern::Any => Ok(true),
+            Pattern::Var(ref n) => {
+                binds.push((n.clone(), val.clone()));
+                Ok(true)
+            },
+            Pattern::Value(ref e) => {
+                let case_val = e.eval(state, f)?;
+                Ok(equal(val, &case_val) == Some(true))
+            },
+            Pattern::Range(ref lo, ref hi) => {
+                let lo = lo.eval(state, f)?;
+                let hi = hi.eval(state, f)?;
+                Ok(in_range(val, &lo, &hi))
+            },
+            Pattern::List(ref items, ref rest) => {
+                let (len, list, rest_val) = match split_list(val, items.len()) {
+                    Some(l) => l,
+                    None => return Ok(false),
+                };
+
+                let size_ok = match *rest {
+                    Some(_) => len >= items.len(),
+                    None => len == items.len(),
+                };
+                if !size_ok {
+                    return Ok(false);
+                }
+
+                for (p, v) in items.iter().zip(list.iter()) {
+                    if !p.bind(v, state, f, binds)? {
+                        return Ok(false);
+                    }
+                }
+
+                match *rest {
+                    Some(ref p) => p.bind(&rest_val, state, f, binds),
+                    None => Ok(true),
+                }
+            },
+            Pattern::Obj(ref fields) => {
+                match *val {
+                    Value::Obj(_,_) | Value::Struct(_,_) => {},
+                    _ => return Ok(false),
+                }
+
+                for (name, p) in fields.iter() {
+                    let field = match get_field(val, name) {
+                        Some(v) => v,
+                        None => return Ok(false),
+                    };
+
+                    if !p.bind(&field, state, f, binds)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+            Pattern::Pair(ref a, ref b) => {
+                let (x, y) = match *val {
+                    Value::Pair(ref x, ref y) => (x.borrow().clone(), y.borrow().clone()),
+                    _ => return Ok(false),
+                };
+
+                Ok(a.bind(&x, state, f, binds)? && b.bind(&y, state, f, binds)?)
+            },
+            Pattern::Enum(ref pk, ref e, ref v, ref payload) => {
+                let vals = match *val {
+                    Value::Enum(ref x, ref p) if x.package == *pk && x.name == *e && x.variant == *v => p.borrow().clone(),
+                    _ => return Ok(false),
+                };
+
+                match *payload {
+                    Some(ref items) if items.len() == vals.len() => {
+                        for (p, v) in items.iter().zip(vals.iter()) {
+                            if !p.bind(v, state, f, binds)? {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    },
+                    Some(_) => Ok(false),
+                    None => Ok(true),
+                }
+            },
+            Pattern::Type(ref n, ref t) => {
+                if type_name(val) != t {
+                    return Ok(false);
+                }
+
+                if let Some(ref n) = *n {
+                    binds.push((n.clone(), val.clone()));
+                }
+                Ok(true)
+            },
+            Pattern::Guard(ref p, ref cond) => {
+                if !p.bind(val, state, f, binds)? {
+                    return Ok(false);
+                }
+
+                state.extend();
+                let res = declare_all(binds.clone(), state).and_then(|_| cond.eval(state, f));
+                state.reduce();
+
+                match truthy(&res?) {
+                    Some(b) => Ok(b),
+                    None => Err(type_error()),
+                }
+            },
+        }
+    }
+}
+
+impl AstNode for Pattern {
+    fn print(&self) -> String {
+        match *self {
+            Pattern::Any => "_".to_string(),
+            Pattern::Var(ref n) => n.clone(),
+            Pattern::Value(ref e) => e.print(),
+            Pattern::Range(ref lo, ref hi) => format!("{}..{}", lo.print(), hi.print()),
+            Pattern::List(ref items, ref rest) => {
+                let mut out = items.iter().map(|p| p.print()).collect::<Vec<_>>();
+                if let Some(ref p) = *rest {
+                    match **p {
+                        Pattern::Any => out.push("..".to_string()),
+                        ref p => out.push(format!("..{}", p.print())),
+                    }
+                }
+                format!("[{}]", out.join(", "))
+            },
+            Pattern::Obj(ref fields) => {
+                let out = fields.iter().map(|(n, p)| match *p {
+                    Pattern::Var(ref v) if v == n => n.clone(),
+                    ref p => format!("{}: {}", n, p.print()),
+                }).collect::<Vec<_>>();
+                format!("{{{}}}", out.join(", "))
+            },
+            Pattern::Pair(ref a, ref b) => format!("<{}, {}>", a.print(), b.print()),
+            Pattern::Enum(_, ref e, ref v, Some(ref items)) => {
+                let out = items.iter().map(|p| p.print()).collect::<Vec<_>>();
+                format!("{}::{}({})", e, v, out.join(", "))
+            },
+            Pattern::Enum(_, ref e, ref v, None) => format!("{}::{}", e, v),
+            Pattern::Type(Some(ref n), ref t) => format!("{} is {}", n, t),
+            Pattern::Type(None, ref t) => format!("is {}", t),
+            Pattern::Guard(ref p, ref cond) => format!("{} if {}", p.print(), cond.print()),
+        }
+    }
+}
+
+
+fn declare_all(binds: Vec<(String, Value)>, state: &mut Scope) -> Result<(), Error> {
+    for (n, v) in binds {
+        if let Signal::Error(e) = state.new_var(&n, v) {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn type_error() -> Error {
+    Error::new(Type::RunTime(RunCode::TypeError))
+}
+
+// Splits a list or range for a list pattern, into its length, up to its first `n` elements, and
+// the elements after those. The rest of a range is another range, so it isn't made into a list.
+fn split_list(val: &Value, n: usize) -> Option<(usize, Vec<Value>, Value)> {
+    match *val {
+        Value::List(ref l) => {
+            let list = l.borrow();
+            let n = cmp::min(n, list.len());
+            let rest = Value::List(Rc::new(RefCell::new(list[n..].to_vec())));
+            Some((list.len(), list[..n].to_vec(), rest))
+        },
+        Value::Range(start, end, step) => {
+            let first = range_iter(start, end, step).take(n).map(|i| Value::Val(VType::I(i))).collect::<Vec<_>>();
+            let rest = match first.last() {
+                Some(&Value::Val(VType::I(i))) => i.saturating_add(step),
+                _ => start,
+            };
+            Some((range_len(start, end, step) as usize, first, Value::Range(rest, end, step)))
+        },
+        _ => None,
+    }
+}
+
+fn in_range(val: &Value, lo: &Value, hi: &Value) -> bool {
+    use self::VType::*;
+
+    fn number(v: &Value) -> Option<VType> {
+        match *v {
+            Value::Val(ref n) => Some(n.clone()),
+            Value::Ref(ref n) => Some(n.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    fn leq(a: &VType, b: &VType) -> Option<bool> {
+        match (a, b) {
+            (I(x), I(y)) => Some(x <= y),
+            (I(x), F(y)) => Some((*x as f64) <= *y),
+            (F(x), I(y)) => Some(*x <= (*y as f64)),
+            (F(x), F(y)) => Some(x <= y),
+            _ => None,
+        }
+    }
+
+    match (number(val), number(lo), number(hi)) {
+        (Some(v), Some(lo), Some(hi)) => leq(&lo, &v) == Some(true) && leq(&v, &hi) == Some(true),
+        _ => false,
+    }
+}
@@ -1,9 +1,11 @@
-use super::{AstNode, Expr, Statement};
-use runtime::{Value, Scope, Signal, ExprRes, FuncMap, PackageRoot};
+use super::{AstNode, Expr, Statement, Resume, call_suspendable};
+use runtime::{Ref, Value, Variant, Scope, Signal, ExprRes, FuncMap, Package, PackageRoot};
 use error::{mserr, Error, Type, RunCode};
 
-use std::collections::BTreeMap;
-use std::{cmp, fmt};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::{cmp, fmt, mem};
 
 // AST entry point for statement snippet
 pub struct Script {
@@ -53,26 +55,177 @@ impl ScriptExpr {
     }
 }
 
+impl AstNode for ScriptExpr {
+    fn print(&self) -> String {
+        match self.expr {
+            Some(ref e) => e.print(),
+            None => String::new(),
+        }
+    }
+}
+
 
 // For packages of functions
 pub struct ScriptPackage {
     pub funcs: BTreeMap<String, FuncRoot>,
+    pub structs: BTreeMap<String, StructRoot>,
+    pub enums: BTreeMap<String, EnumRoot>,
+    pub warnings: Vec<Error>,
 }
 
 impl ScriptPackage {
-    pub fn new(f: BTreeMap<String, FuncRoot>) -> Self {
+    pub fn new(f: BTreeMap<String, FuncRoot>, s: BTreeMap<String, StructRoot>, e: BTreeMap<String, EnumRoot>) -> Self {
         ScriptPackage {
             funcs: f,
+            structs: s,
+            enums: e,
+            warnings: Vec::new(),
         }
     }
 
     pub fn call_ref(self) -> PackageRoot {
-        Box::new(move |n, a, f| {
-            match self.funcs.get(n) {
-                Some(func) => func.call(a, f, None),
-                None => Err(Error::new(Type::RunTime(RunCode::FunctionNotFound))),
-            }
-        })
+        Box::new(self)
+    }
+
+    fn call_with(&self, n: &str, this: Option<&Value>, a: &[Value], f: &FuncMap) -> ExprRes {
+        match self.funcs.get(n) {
+            Some(func) => func.call(a, f, None, this),
+            None => Err(Error::new(Type::RunTime(RunCode::FunctionNotFound))),
+        }
+    }
+}
+
+impl Package for ScriptPackage {
+    fn call(&self, name: &str, args: &[Value], f: &FuncMap) -> ExprRes {
+        self.call_with(name, None, args, f)
+    }
+
+    // Methods get the object as `self`.
+    fn call_method(&self, name: &str, this: &Value, args: &[Value], f: &FuncMap) -> ExprRes {
+        self.call_with(name, Some(this), args, f)
+    }
+
+    fn call_suspendable(&self, name: &str, this: Option<&Value>, args: &[Value], f: &FuncMap) -> Result<Called, Error> {
+        match self.funcs.get(name) {
+            Some(func) => func.call_suspendable(args, f, None, this),
+            None => self.call_with(name, this, args, f).map(Called::Returned),
+        }
+    }
+
+    fn construct_struct(&self, name: &str, fields: &Value, f: &FuncMap) -> ExprRes {
+        match self.structs.get(name) {
+            Some(st) => st.construct(fields, f),
+            None => mserr(Type::RunTime(RunCode::StructNotFound)),
+        }
+    }
+
+    fn construct_variant(&self, name: &str, variant: &str, args: &[Value]) -> ExprRes {
+        match self.enums.get(name) {
+            Some(en) => en.construct(variant, args),
+            None => mserr(Type::RunTime(RunCode::EnumNotFound)),
+        }
+    }
+}
+
+
+// AST entry point for struct declaration
+pub struct StructRoot {
+    name: Rc<str>,
+    fields: Vec<(String, Option<Box<dyn Expr>>)>,
+}
+
+impl StructRoot {
+    pub fn new(name: String, fields: Vec<(String, Option<Box<dyn Expr>>)>) -> Self {
+        StructRoot {
+            name: name.into(),
+            fields,
+        }
+    }
+
+    // Builds an instance from an object of field values. Fields that aren't given take
+    // their default value, and fields that aren't declared are rejected.
+    pub fn construct(&self, fields: &Value, f: &FuncMap) -> ExprRes {
+        let mut given = match *fields {
+            Value::Obj(ref o, _) => o.borrow().clone(),
+            _ => return mserr(Type::RunTime(RunCode::TypeError)),
+        };
+
+        if given.keys().any(|k| !self.fields.iter().any(|(n, _)| n == k)) {
+            return mserr(Type::RunTime(RunCode::UnknownField));
+        }
+
+        let mut state = Scope::new();
+        let mut fields = HashMap::new();
+
+        for (n, default) in self.fields.iter() {
+            let val = match (given.remove(n), default) {
+                (Some(v), _) => v,
+                (None, Some(e)) => e.eval(&mut state, f)?,
+                (None, None) => return mserr(Type::RunTime(RunCode::MissingField)),
+            };
+
+            fields.insert(n.clone(), val);
+        }
+
+        Ok(Value::Struct(self.name.clone(), Rc::new(RefCell::new(fields))))
+    }
+}
+
+impl AstNode for StructRoot {
+    fn print(&self) -> String {
+        let fields = self.fields.iter().map(|(n, d)| match *d {
+            Some(ref e) => format!("{} = {}", n, e.print()),
+            None => n.clone(),
+        }).collect::<Vec<_>>();
+        format!("struct {} {{{}}}", self.name, fields.join(", "))
+    }
+}
+
+
+// AST entry point for enum declaration
+pub struct EnumRoot {
+    name: String,
+    variants: Vec<(Rc<Variant>, Vec<String>)>,
+}
+
+impl EnumRoot {
+    pub fn new(package: String, name: String, variants: Vec<(String, Vec<String>)>) -> Self {
+        let variants = variants.into_iter().map(|(variant, fields)| {
+            let v = Variant {
+                package: package.clone(),
+                name: name.clone(),
+                variant,
+            };
+            (Rc::new(v), fields)
+        }).collect();
+
+        EnumRoot {
+            name,
+            variants,
+        }
+    }
+
+    pub fn variant_names(&self) -> Vec<String> {
+        self.variants.iter().map(|(v, _)| v.variant.clone()).collect()
+    }
+
+    // Builds an instance of the variant, holding the arguments as its payload.
+    pub fn construct(&self, variant: &str, args: &[Value]) -> ExprRes {
+        match self.variants.iter().find(|(v, _)| v.variant == variant) {
+            Some((v, fields)) if fields.len() == args.len() => Ok(Value::Enum(v.clone(), Rc::new(RefCell::new(args.to_vec())))),
+            Some(_) => mserr(Type::RunTime(RunCode::WrongNumberOfArguments)),
+            None => mserr(Type::RunTime(RunCode::VariantNotFound)),
+        }
+    }
+}
+
+impl AstNode for EnumRoot {
+    fn print(&self) -> String {
+        let variants = self.variants.iter().map(|(v, fields)| match fields.len() {
+            0 => v.variant.clone(),
+            _ => format!("{}({})", v.variant, fields.join(", ")),
+        }).collect::<Vec<_>>();
+        format!("enum {} {{{}}}", self.name, variants.join(", "))
     }
 }
 
@@ -80,22 +233,59 @@ impl ScriptPackage {
 // AST entry point for function
 pub struct FuncRoot {
     arg_names: Vec<String>,
+    // Functions containing `yield` return a generator instead of running when called
+    generator: bool,
+    body: Rc<FuncBody>,
+}
+
+// Statements of a function, shared with its suspended calls, and where it was declared.
+struct FuncBody {
     stat_list: Vec<Box<dyn Statement>>,
+    package: String,
+    name: String,
+    file: Option<String>,
 }
 
 impl FuncRoot {
-    pub fn new(arg_names: Vec<String>, stat_list: Vec<Box<dyn Statement>>) -> Self {
+    pub fn new(arg_names: Vec<String>, stat_list: Vec<Box<dyn Statement>>, package: String, name: String, file: Option<String>) -> Self {
         FuncRoot {
             arg_names: arg_names,
-            stat_list: stat_list,
+            generator: stat_list.iter().any(|s| s.can_yield()),
+            body: Rc::new(FuncBody {
+                stat_list,
+                package,
+                name,
+                file,
+            }),
+        }
+    }
+
+    // Calls the function with any captured variables, and the object it is a method of as `self`.
+    pub fn call(&self, args: &[Value], f: &FuncMap, scope: Option<&[(String, Value)]>, this: Option<&Value>) -> ExprRes {
+        let mut frame = self.enter(args, scope, this).map_err(|e| self.body.trace(e))?;
+
+        if self.generator {
+            return Ok(Value::Gen(Generator::start(frame)));
+        }
+        returned(frame.run(f)).map_err(|e| self.body.trace(e))
+    }
+
+    // Calls the function so that a yield inside it suspends the call rather than failing.
+    pub fn call_suspendable(&self, args: &[Value], f: &FuncMap, scope: Option<&[(String, Value)]>, this: Option<&Value>) -> Result<Called, Error> {
+        let frame = self.enter(args, scope, this).map_err(|e| self.body.trace(e))?;
+
+        if self.generator {
+            return Ok(Called::Generator(Generator::start(frame)));
         }
+        frame.resume(f)
     }
 
-    pub fn call(&self, args: &[Value], f: &FuncMap, scope: Option<&[(String, Value)]>) -> ExprRes {
+    // Sets up the variables of a call, ready to run the body.
+    fn enter(&self, args: &[Value], scope: Option<&[(String, Value)]>, this: Option<&Value>) -> Result<Frame, Error> {
         let mut state = Scope::new();
 
         if args.len() != self.arg_names.len() {
-            return mserr(Type::RunTime(RunCode::WrongNumberOfArguments));
+            return Err(Error::new(Type::RunTime(RunCode::WrongNumberOfArguments)));
         }
 
         if let Some(s) = scope {
@@ -106,25 +296,226 @@ impl FuncRoot {
             state.extend();
         }
 
+        if let Some(t) = this {
+            state.new_var("self", t.clone());
+            state.extend();
+        }
+
         for (a,n) in args.iter().zip(self.arg_names.iter()) {
             state.new_var(&n, a.clone());
         }
 
-        for s in &self.stat_list {
-            match s.run(&mut state, f) {
+        Ok(Frame {
+            body: self.body.clone(),
+            state,
+        })
+    }
+
+    pub fn get_arg_names(&self) -> &[String] {
+        self.arg_names.as_slice()
+    }
+}
+
+impl FuncBody {
+    // Adds where the function is to an error coming out of it.
+    fn trace(&self, e: Error) -> Error {
+        let e = match self.file {
+            Some(ref file) => e.in_file(file),
+            None => e,
+        };
+        e.in_function(&self.package, &self.name)
+    }
+}
+
+// Value a function gives back when its body stops with the signal.
+fn returned(s: Signal) -> ExprRes {
+    match s {
+        Signal::Done => Ok(Value::Null),
+        Signal::Return(v) => Ok(v),
+        Signal::Error(e) => Err(e),
+        Signal::Throw(v) => Err(Error::new(Type::Exception(v))),
+        Signal::Continue => mserr(Type::RunTime(RunCode::CannotContinue)),
+        Signal::Break => mserr(Type::RunTime(RunCode::CannotBreak)),
+        Signal::Yield(_) => mserr(Type::RunTime(RunCode::CannotYield)),
+    }
+}
+
+// Body of a function call along with its variables, which can be suspended by a yield.
+pub struct Frame {
+    body: Rc<FuncBody>,
+    state: Scope,
+}
+
+// Outcome of a call that can be suspended.
+pub enum Called {
+    Returned(Value),
+    Suspended(Frame, Value),
+    // A native function returned the first value, and asked for the second to be yielded
+    // once the statement calling it is done.
+    Requested(Value, Value),
+    // A generator function gave its generator instead of running.
+    Generator(Ref<Generator>),
+}
+
+impl Frame {
+    // Runs the body, from where it was suspended if it was.
+    fn run(&mut self, f: &FuncMap) -> Signal {
+        let start = match self.state.pop_resume() {
+            Some(Resume::Block(i)) => i,
+            _ => 0,
+        };
+
+        for (i, s) in self.body.stat_list.iter().enumerate().skip(start) {
+            match s.run(&mut self.state, f) {
                 Signal::Done => {},
-                Signal::Error(e) => return Err(e),
-                Signal::Return(v) => return Ok(v),
-                Signal::Continue => return mserr(Type::RunTime(RunCode::CannotContinue)),
-                Signal::Break => return mserr(Type::RunTime(RunCode::CannotBreak)),
+                Signal::Yield(v) => {
+                    self.state.push_resume(Resume::Block(i));
+                    return Signal::Yield(v);
+                },
+                s => return s,
             }
         }
 
-        Ok(Value::Null)
+        Signal::Done
     }
 
-    pub fn get_arg_names(&self) -> &[String] {
-        self.arg_names.as_slice()
+    // Carries on a suspended call, until it returns or yields again.
+    pub fn resume(mut self, f: &FuncMap) -> Result<Called, Error> {
+        match self.run(f) {
+            Signal::Yield(v) => Ok(Called::Suspended(self, v)),
+            s => returned(s).map(Called::Returned).map_err(|e| self.body.trace(e)),
+        }
+    }
+}
+
+
+// Call of a generator function, which runs up to its next `yield` each time it is resumed.
+pub struct Generator {
+    frame: Option<Frame>,
+    running: bool,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Resumed {
+    Yielded(Value),
+    Finished(Value),
+}
+
+impl Generator {
+    fn start(frame: Frame) -> Ref<Generator> {
+        Rc::new(RefCell::new(Generator {
+            frame: Some(frame),
+            running: false,
+        }))
+    }
+
+    // Runs the generator until it yields a value, or finishes with the value it returns.
+    // Resuming a generator that has finished gives null.
+    pub fn resume(gen: &Ref<Generator>, f: &FuncMap) -> Result<Resumed, Error> {
+        let frame = {
+            let mut g = gen.borrow_mut();
+            if g.running {
+                return Err(Error::new(Type::RunTime(RunCode::GeneratorRunning)));
+            }
+            g.running = g.frame.is_some();
+            g.frame.take()
+        };
+
+        let res = match frame {
+            Some(frame) => frame.resume(f),
+            None => return Ok(Resumed::Finished(Value::Null)),
+        };
+
+        let mut g = gen.borrow_mut();
+        g.running = false;
+        match res? {
+            Called::Suspended(frame, v) => {
+                g.frame = Some(frame);
+                Ok(Resumed::Yielded(v))
+            },
+            Called::Returned(v) => Ok(Resumed::Finished(v)),
+            Called::Generator(g) => Ok(Resumed::Finished(Value::Gen(g))),
+            Called::Requested(_, _) => Err(Error::new(Type::RunTime(RunCode::CannotYield))),
+        }
+    }
+}
+
+impl cmp::PartialEq for Generator {
+    fn eq(&self, _: &Generator) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "generator")
+    }
+}
+
+
+// Script function run from rust a step at a time, such as a script spread over the frames of a
+// game. It runs until a native function asks it to yield with `FuncMap::request_yield`, which
+// suspends any script functions it is inside too. Generators can be run this way as well.
+pub struct Coroutine {
+    step: Step,
+}
+
+enum Step {
+    Start(Value),
+    Call(Frame),
+    Gen(Ref<Generator>),
+    Finished,
+}
+
+impl Coroutine {
+    // Takes a function to call with no arguments, or a generator.
+    pub fn new(func: Value) -> Self {
+        Coroutine {
+            step: match func {
+                Value::Gen(g) => Step::Gen(g),
+                func => Step::Start(func),
+            },
+        }
+    }
+
+    // Runs the coroutine until it yields a value, or finishes with the value it returns.
+    // Resuming a coroutine that has finished, or failed, gives null.
+    pub fn resume(&mut self, f: &FuncMap) -> Result<Resumed, Error> {
+        f.without_yield_request(|| self.step(f)).0
+    }
+
+    fn step(&mut self, f: &FuncMap) -> Result<Resumed, Error> {
+        let called = match mem::replace(&mut self.step, Step::Finished) {
+            Step::Start(func) => call_suspendable(func, None, &[], f)?,
+            Step::Call(frame) => frame.resume(f)?,
+            Step::Gen(g) => {
+                let res = Generator::resume(&g, f);
+                if let Ok(Resumed::Yielded(_)) = res {
+                    self.step = Step::Gen(g);
+                }
+                return res;
+            },
+            Step::Finished => return Ok(Resumed::Finished(Value::Null)),
+        };
+
+        match called {
+            Called::Suspended(frame, v) => {
+                self.step = Step::Call(frame);
+                Ok(Resumed::Yielded(v))
+            },
+            // A generator function gives a generator to run instead.
+            Called::Generator(g) => {
+                self.step = Step::Gen(g);
+                self.step(f)
+            },
+            Called::Returned(v) => Ok(Resumed::Finished(v)),
+            // Only a script function can be suspended.
+            Called::Requested(_, _) => Err(Error::new(Type::RunTime(RunCode::CannotYield))),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.step, Step::Finished)
     }
 }
 
@@ -1,62 +1,147 @@
-use super::{AstNode, Statement, Expr, Assign};
-use runtime::{Value, VType, Scope, Signal, FuncMap, equal};
-use error::{Error, Type, RunCode};
+use super::{AstNode, Statement, Expr, Assign, Pattern, Generator, Resumed, Frame, Called, call_hook};
+use runtime::{Ref, Value, VType, Scope, Signal, FuncMap, truthy, get_field, range_iter};
+use error::{Error, Type, RunCode, Span};
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::iter;
 
 pub struct ScopeStat {
     code: Vec<Box<dyn Statement>>,
 }
 
 pub struct VarDecl {
-    name: String,
+    pattern: Pattern,
     assign: Option<Box<dyn Expr>>,
+    span: Span,
 }
 
 pub struct AssignStat {
     name: String,
     assign: Box<dyn Expr>,
     child_op: Option<Box<dyn Assign>>,
+    span: Span,
+}
+
+pub struct DestructureStat {
+    pattern: Pattern,
+    assign: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct ExprStat {
     expr: Box<dyn Expr>,
+    span: Span,
 }
 
 pub struct IfStat {
     cond: Box<dyn Expr>,
     then_stat: Box<dyn Statement>,
     else_stat: Option<Box<dyn Statement>>,
-}
-
-pub enum CaseType {
-    Var(String),
-    Value(Box<dyn Expr>),
+    span: Span,
 }
 
 pub struct MatchStat {
     cond: Box<dyn Expr>,
-    cases: Vec<(CaseType, Box<dyn Statement>)>,
-    otherwise: Option<Box<dyn Statement>>
+    cases: Vec<(Pattern, Box<dyn Statement>)>,
+    otherwise: Option<Box<dyn Statement>>,
+    span: Span,
 }
 
 pub struct WhileStat {
     cond: Box<dyn Expr>,
     loop_body: Box<dyn Statement>,
+    span: Span,
 }
 
 pub struct ForStat {
+    k_name: Option<String>,
     e_name: String,
     list: Box<dyn Expr>,
     loop_body: Box<dyn Statement>,
+    span: Span,
 }
 
 pub struct ReturnStat {
     expr: Option<Box<dyn Expr>>,
+    span: Span,
+}
+
+pub struct TryStat {
+    try_stat: Box<dyn Statement>,
+    e_name: String,
+    catch_stat: Box<dyn Statement>,
+}
+
+pub struct ThrowStat {
+    expr: Box<dyn Expr>,
+    span: Span,
+}
+
+pub struct YieldStat {
+    expr: Option<Box<dyn Expr>>,
+    span: Span,
 }
 
 pub struct ContinueStat {}
 
 pub struct BreakStat {}
 
+// Where a statement was when a `yield` inside it suspended the function. Each statement the
+// yield passes through pushes its own, and takes it back when the function is resumed.
+pub enum Resume {
+    Yield,
+    Return(Value),
+    Call(Frame),
+    Block(usize),
+    Branch(bool),
+    Case(usize),
+    Loop,
+    For(ForIter),
+    Try(bool),
+}
+
+
+// Value of the expression a statement is made of, and the value a native function called by
+// it asked to yield, if any. A call making up the whole expression is suspended along with the
+// statement when it yields, and finished when it is resumed.
+fn eval_stat(expr: &dyn Expr, state: &mut Scope, f: &FuncMap, span: &Span) -> Result<(Value, Option<Value>), Signal> {
+    let called = match state.pop_resume() {
+        // The statement was already done when it yielded.
+        Some(Resume::Yield) => return Err(Signal::Done),
+        Some(Resume::Return(v)) => return Err(Signal::Return(v)),
+        Some(Resume::Call(frame)) => frame.resume(f),
+        _ => expr.eval_call(state, f),
+    };
+
+    match called {
+        Ok(Called::Returned(v)) => Ok((v, None)),
+        Ok(Called::Requested(v, y)) => Ok((v, Some(y))),
+        Ok(Called::Generator(g)) => Ok((Value::Gen(g), None)),
+        Ok(Called::Suspended(frame, v)) => {
+            state.push_resume(Resume::Call(frame));
+            Err(Signal::Yield(v))
+        },
+        Err(e) => Err(Signal::Error(e.at(span))),
+    }
+}
+
+// Yields the value a native function asked for once the statement is done. Resuming
+// carries on from how the statement finished.
+fn yield_after(s: Signal, request: Option<Value>, state: &mut Scope) -> Signal {
+    match (s, request) {
+        (Signal::Done, Some(y)) => {
+            state.push_resume(Resume::Yield);
+            Signal::Yield(y)
+        },
+        (Signal::Return(v), Some(y)) => {
+            state.push_resume(Resume::Return(v));
+            Signal::Yield(y)
+        },
+        (s, _) => s,
+    }
+}
+
 
 // IMPLS
 
@@ -76,11 +161,15 @@ impl AstNode for ScopeStat {
 
 impl Statement for ScopeStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        state.extend();
+        let start = match state.pop_resume() {
+            Some(Resume::Block(i)) => i,
+            _ => {state.extend(); 0},
+        };
 
-        for s in &self.code {
+        for (i, s) in self.code.iter().enumerate().skip(start) {
             match s.run(state, f) {
                 Signal::Done => {},
+                Signal::Yield(v) => {state.push_resume(Resume::Block(i)); return Signal::Yield(v);},
                 s => {state.reduce(); return s;},
             }
         }
@@ -88,14 +177,19 @@ impl Statement for ScopeStat {
         state.reduce();
         Signal::Done
     }
+
+    fn can_yield(&self) -> bool {
+        self.code.iter().any(|s| s.can_yield())
+    }
 }
 
 
 impl VarDecl {
-    pub fn new(n: &str, a: Option<Box<dyn Expr>>) -> Self {
+    pub fn new(p: Pattern, a: Option<Box<dyn Expr>>, s: Span) -> Self {
         VarDecl {
-            name: n.to_string(),
+            pattern: p,
             assign: a,
+            span: s,
         }
     }
 }
@@ -108,25 +202,31 @@ impl AstNode for VarDecl {
 
 impl Statement for VarDecl {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        let val = match self.assign {
-            Some(ref e) => match e.eval(state, f) {
+        let (val, request) = match self.assign {
+            Some(ref e) => match eval_stat(&**e, state, f, &self.span) {
                 Ok(v) => v,
-                Err(e) => return Signal::Error(e),
+                Err(s) => return s,
             },
-            None => Value::Null,
+            None => (Value::Null, None),
+        };
+
+        let res = match self.pattern.declare(&val, state, f) {
+            Ok(()) => Signal::Done,
+            Err(e) => Signal::Error(e.at(&self.span)),
         };
 
-        state.new_var(&self.name, val)
+        yield_after(res, request, state)
     }
 }
 
 
 impl AssignStat {
-    pub fn new(n: &str, a: Box<dyn Expr>, c: Option<Box<dyn Assign>>) -> Self {
+    pub fn new(n: &str, a: Box<dyn Expr>, c: Option<Box<dyn Assign>>, s: Span) -> Self {
         AssignStat {
             name: n.to_string(),
             assign: a,
             child_op: c,
+            span: s,
         }
     }
 }
@@ -141,26 +241,62 @@ impl Statement for AssignStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
         let var = match state.get_var(&self.name) {
             Ok(v) => v,
-            Err(e) => return Signal::Error(e),
+            Err(e) => return Signal::Error(e.at(&self.span)),
         };
 
-        let val = match self.assign.eval(state, f) {
+        let (val, request) = match eval_stat(&*self.assign, state, f, &self.span) {
             Ok(v) => v,
-            Err(e) => return Signal::Error(e),
+            Err(s) => return s,
         };
 
-        match self.child_op {
+        let res = match self.child_op {
             Some(ref o) => o.assign(var, val, state, f),
             None    => state.set_var(&self.name, val),
+        };
+
+        yield_after(res.at(&self.span), request, state)
+    }
+}
+
+
+impl DestructureStat {
+    pub fn new(p: Pattern, a: Box<dyn Expr>, s: Span) -> Self {
+        DestructureStat {
+            pattern: p,
+            assign: a,
+            span: s,
         }
     }
 }
 
+impl AstNode for DestructureStat {
+    fn print(&self) -> String {
+        "scope".to_string()
+    }
+}
+
+impl Statement for DestructureStat {
+    fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
+        let (val, request) = match eval_stat(&*self.assign, state, f, &self.span) {
+            Ok(v) => v,
+            Err(s) => return s,
+        };
+
+        let res = match self.pattern.assign(&val, state, f) {
+            Ok(()) => Signal::Done,
+            Err(e) => Signal::Error(e.at(&self.span)),
+        };
+
+        yield_after(res, request, state)
+    }
+}
+
 
 impl ExprStat {
-    pub fn new(e: Box<dyn Expr>) -> Self {
+    pub fn new(e: Box<dyn Expr>, s: Span) -> Self {
         ExprStat {
             expr: e,
+            span: s,
         }
     }
 }
@@ -173,20 +309,21 @@ impl AstNode for ExprStat {
 
 impl Statement for ExprStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        match self.expr.eval(state, f) {
-            Ok(_) => Signal::Done,
-            Err(e) => Signal::Error(e),
+        match eval_stat(&*self.expr, state, f, &self.span) {
+            Ok((_, request)) => yield_after(Signal::Done, request, state),
+            Err(s) => s,
         }
     }
 }
 
 
 impl IfStat {
-    pub fn new(c: Box<dyn Expr>, i: Box<dyn Statement>, e: Option<Box<dyn Statement>>) -> Self {
+    pub fn new(c: Box<dyn Expr>, i: Box<dyn Statement>, e: Option<Box<dyn Statement>>, s: Span) -> Self {
         IfStat {
             cond: c,
             then_stat: i,
             else_stat: e,
+            span: s,
         }
     }
 }
@@ -199,32 +336,46 @@ impl AstNode for IfStat {
 
 impl Statement for IfStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        let c = match self.cond.eval(state, f) {
-            Ok(v) => v,
-            Err(e) => return Signal::Error(e),
+        let branch = match state.pop_resume() {
+            Some(Resume::Branch(b)) => b,
+            _ => {
+                let c = match self.cond.eval(state, f) {
+                    Ok(v) => v,
+                    Err(e) => return Signal::Error(e.at(&self.span)),
+                };
+
+                match truthy(&c) {
+                    Some(b) => b,
+                    None => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError)).at(&self.span)),
+                }
+            },
         };
 
-        match c {
-            Value::Val(VType::B(true)) => return self.then_stat.run(state, f),
-            Value::Val(VType::B(false)) => {},
-            Value::Val(VType::I(i)) => if i != 0 {return self.then_stat.run(state, f)},
-            _ => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError))),
-        }
+        let ret = match (branch, &self.else_stat) {
+            (true, _) => self.then_stat.run(state, f),
+            (false, Some(s)) => s.run(state, f),
+            (false, None) => Signal::Done,
+        };
 
-        match self.else_stat {
-            Some(ref s) => s.run(state, f),
-            None => Signal::Done,
+        if let Signal::Yield(_) = ret {
+            state.push_resume(Resume::Branch(branch));
         }
+        ret
+    }
+
+    fn can_yield(&self) -> bool {
+        self.then_stat.can_yield() || self.else_stat.iter().any(|s| s.can_yield())
     }
 }
 
 
 impl MatchStat {
-    pub fn new(m: Box<dyn Expr>, c: Vec<(CaseType, Box<dyn Statement>)>, o: Option<Box<dyn Statement>>) -> Self {
+    pub fn new(m: Box<dyn Expr>, c: Vec<(Pattern, Box<dyn Statement>)>, o: Option<Box<dyn Statement>>, s: Span) -> Self {
         MatchStat {
             cond: m,
             cases: c,
             otherwise: o,
+            span: s,
         }
     }
 }
@@ -237,47 +388,53 @@ impl AstNode for MatchStat {
 
 impl Statement for MatchStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        let c = match self.cond.eval(state, f) {
-            Ok(v) => v,
-            Err(e) => return Signal::Error(e),
-        };
-
-        for (case, stat) in self.cases.iter() {
-            match case {
-                CaseType::Var(ref v)    => {
-                    state.extend();
-                    state.new_var(v, c.clone());
-                    let ret = stat.run(state, f);
-                    state.reduce();
-                    return ret;
-                }
-                CaseType::Value(ref v)  => {
-                    let val = match v.eval(state, f) {
-                        Ok(v) => v,
-                        Err(e) => return Signal::Error(e),
-                    };
-
-                    match equal(&c, &val) {
-                        Some(true) => return stat.run(state, f),
-                        _ => (),
+        // The else case comes after the others.
+        let case = match state.pop_resume() {
+            Some(Resume::Case(i)) => i,
+            _ => {
+                let c = match self.cond.eval(state, f) {
+                    Ok(v) => v,
+                    Err(e) => return Signal::Error(e.at(&self.span)),
+                };
+
+                let mut found = self.cases.len();
+                for (i, (case, _)) in self.cases.iter().enumerate() {
+                    match case.matches(&c, state, f) {
+                        Ok(true) => {found = i; break;},
+                        Ok(false) => {},
+                        Err(e) => return Signal::Error(e.at(&self.span)),
                     }
                 }
-            }
-        }
+                found
+            },
+        };
+
+        let ret = match (self.cases.get(case), &self.otherwise) {
+            (Some((_, stat)), _) => stat.run(state, f),
+            (None, Some(s)) => s.run(state, f),
+            (None, None) => Signal::Done,
+        };
 
-        match self.otherwise {
-            Some(ref s) => s.run(state, f),
-            None => Signal::Done,
+        match ret {
+            Signal::Yield(v) => {state.push_resume(Resume::Case(case)); Signal::Yield(v)},
+            // Cases declare the names they bind in a scope of their own.
+            s if case < self.cases.len() => {state.reduce(); s},
+            s => s,
         }
     }
+
+    fn can_yield(&self) -> bool {
+        self.cases.iter().any(|(_, s)| s.can_yield()) || self.otherwise.iter().any(|s| s.can_yield())
+    }
 }
 
 
 impl WhileStat {
-    pub fn new(c: Box<dyn Expr>, b: Box<dyn Statement>) -> Self {
+    pub fn new(c: Box<dyn Expr>, b: Box<dyn Statement>, s: Span) -> Self {
         WhileStat {
             cond: c,
             loop_body: b,
+            span: s,
         }
     }
 }
@@ -290,36 +447,47 @@ impl AstNode for WhileStat {
 
 impl Statement for WhileStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
+        let mut resumed = matches!(state.pop_resume(), Some(Resume::Loop));
+
         loop {
-            match self.cond.eval(state, f) {
-                Ok(v) => match v {
-                    Value::Val(VType::B(true)) => {},
-                    Value::Val(VType::B(false)) => break,
-                    Value::Val(VType::I(i)) => if i == 0 {break},
-                    _ => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError))),
-                },
-                Err(e) => return Signal::Error(e),
+            if !resumed {
+                match self.cond.eval(state, f) {
+                    Ok(v) => match truthy(&v) {
+                        Some(true) => {},
+                        Some(false) => break,
+                        None => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError)).at(&self.span)),
+                    },
+                    Err(e) => return Signal::Error(e.at(&self.span)),
+                }
             }
+            resumed = false;
 
             match self.loop_body.run(state, f) {
                 Signal::Done => {},
                 Signal::Continue => {},
                 Signal::Break => break,
+                Signal::Yield(v) => {state.push_resume(Resume::Loop); return Signal::Yield(v);},
                 s => return s,
             }
         }
 
         Signal::Done
     }
+
+    fn can_yield(&self) -> bool {
+        self.loop_body.can_yield()
+    }
 }
 
 
 impl ForStat {
-    pub fn new(e: String, l: Box<dyn Expr>, b: Box<dyn Statement>) -> Self {
+    pub fn new(k: Option<String>, e: String, l: Box<dyn Expr>, b: Box<dyn Statement>, s: Span) -> Self {
         ForStat {
+            k_name: k,
             e_name: e,
             list: l,
             loop_body: b,
+            span: s,
         }
     }
 }
@@ -332,59 +500,144 @@ impl AstNode for ForStat {
 
 impl Statement for ForStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
-        match self.list.eval(state, f) {
-            Ok(v) => match v {
-                Value::List(l) => {
-                    state.extend();
-                    state.new_var(&self.e_name, Value::Null);
-
-                    for e in l.borrow().iter() {
-                        state.set_var(&self.e_name, e.clone());
-
-                        match self.loop_body.run(state, f) {
-                            Signal::Done => {},
-                            Signal::Continue => {},
-                            Signal::Break => break,
-                            s => {state.reduce(); return s},
-                        }
-                    }
+        let (mut items, mut resumed) = match state.pop_resume() {
+            Some(Resume::For(items)) => (items, true),
+            _ => {
+                let items = match self.list.eval(state, f) {
+                    Ok(v) => match ForIter::new(&v) {
+                        Some(items) => items,
+                        None => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError)).at(&self.span)),
+                    },
+                    Err(e) => return Signal::Error(e.at(&self.span)),
+                };
+
+                state.extend();
+                state.new_var(&self.e_name, Value::Null);
+                if let Some(ref k) = self.k_name {
+                    state.new_var(k, Value::Null);
+                }
+                (items, false)
+            },
+        };
 
-                    state.reduce();
+        loop {
+            if !resumed {
+                let (k, e) = match items.next(f) {
+                    Some(Ok(item)) => item,
+                    Some(Err(e)) => {state.reduce(); return Signal::Error(e.at(&self.span))},
+                    None => break,
+                };
+
+                // With a single name, objects and maps give their keys.
+                match self.k_name {
+                    Some(ref n) => {
+                        state.set_var(n, k);
+                        state.set_var(&self.e_name, e);
+                    },
+                    None if items.keyed() => {state.set_var(&self.e_name, k);},
+                    None => {state.set_var(&self.e_name, e);},
+                }
+            }
+            resumed = false;
 
-                    Signal::Done
-                },
-                /*Value::Str(s) => {
-                    state.extend();
-                    state.new_var(&self.e_name, Value::Null);
-
-                    for e in l.borrow().iter() {
-                        state.set_var(&self.e_name, e.clone());
-
-                        match self.loop_body.run(state, f) {
-                            Signal::Done => {},
-                            Signal::Continue => {},
-                            Signal::Break => break,
-                            s => {state.reduce(); return s},
-                        }
-                    }
+            match self.loop_body.run(state, f) {
+                Signal::Done => {},
+                Signal::Continue => {},
+                Signal::Break => break,
+                Signal::Yield(v) => {state.push_resume(Resume::For(items)); return Signal::Yield(v);},
+                s => {state.reduce(); return s},
+            }
+        }
 
-                    state.reduce();
+        state.reduce();
 
-                    Signal::Done
-                },*/
-                _ => return Signal::Error(Error::new(Type::RunTime(RunCode::TypeError))),
-            },
-            Err(e) => return Signal::Error(e),
+        Signal::Done
+    }
+
+    fn can_yield(&self) -> bool {
+        self.loop_body.can_yield()
+    }
+}
+
+// The (key, element) pairs a for loop goes through, and how far it has got.
+pub enum ForIter {
+    // Lists, strings, objects and maps are read before the loop starts, giving the indexes and
+    // elements of a list, the indexes and characters of a string, or the keys and values of an
+    // object (in order of key) or map. Ranges and rust iterators are read as the loop goes,
+    // with a count from 0. Also whether a single name gets the key.
+    Values(Box<dyn Iterator<Item = (Value, Value)>>, bool),
+    // Objects with a `__next` hook, called until it returns null.
+    Hook(Value, usize),
+    Gen(Ref<Generator>, usize),
+}
+
+impl ForIter {
+    pub fn new(v: &Value) -> Option<Self> {
+        let index = |i: usize| Value::Val(VType::I(i as i64));
+
+        if get_field(v, "__next").is_some() {
+            return Some(ForIter::Hook(v.clone(), 0));
         }
 
+        let items: Vec<(Value, Value)> = match *v {
+            Value::Gen(ref g) => return Some(ForIter::Gen(g.clone(), 0)),
+            Value::Range(start, end, step) => {
+                let range = range_iter(start, end, step).enumerate().map(move |(i, n)| (index(i), Value::Val(VType::I(n))));
+                return Some(ForIter::Values(Box::new(range), false));
+            },
+            Value::Iter(ref n) => {
+                let n = n.clone();
+                let values = iter::from_fn(move || n.borrow_mut().next());
+                return Some(ForIter::Values(Box::new(values.enumerate().map(move |(i, e)| (index(i), e))), false));
+            },
+            Value::List(ref l) => l.borrow().iter().cloned().enumerate()
+                .map(|(i, e)| (index(i), e))
+                .collect(),
+            Value::Str(ref s) => s.borrow().chars().enumerate()
+                .map(|(i, c)| (index(i), Value::Str(Rc::new(RefCell::new(c.to_string())))))
+                .collect(),
+            Value::Obj(ref o, _) => {
+                let mut fields = o.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                fields.into_iter().map(|(k, v)| (Value::Str(Rc::new(RefCell::new(k))), v)).collect()
+            },
+            Value::Map(ref m) => m.borrow().values().cloned().collect(),
+            _ => return None,
+        };
+
+        let keyed = matches!(*v, Value::Obj(..) | Value::Map(_));
+        Some(ForIter::Values(Box::new(items.into_iter()), keyed))
+    }
+
+    pub fn next(&mut self, f: &FuncMap) -> Option<Result<(Value, Value), Error>> {
+        let (next, count) = match *self {
+            ForIter::Values(ref mut values, _) => return values.next().map(Ok),
+            ForIter::Hook(ref v, ref mut count) => match call_hook(v, "__next", &[], f)? {
+                Ok(Value::Null) => return None,
+                res => (res, count),
+            },
+            ForIter::Gen(ref g, ref mut count) => match Generator::resume(g, f) {
+                Ok(Resumed::Yielded(v)) => (Ok(v), count),
+                Ok(Resumed::Finished(_)) => return None,
+                Err(e) => (Err(e), count),
+            },
+        };
+
+        *count += 1;
+        Some(next.map(|v| (Value::Val(VType::I(*count as i64 - 1)), v)))
+    }
+
+    fn keyed(&self) -> bool {
+        matches!(*self, ForIter::Values(_, true))
     }
 }
 
 
 impl ReturnStat {
-    pub fn new(e: Option<Box<dyn Expr>>) -> Self {
+    pub fn new(e: Option<Box<dyn Expr>>, s: Span) -> Self {
         ReturnStat {
             expr: e,
+            span: s,
         }
     }
 }
@@ -398,15 +651,130 @@ impl AstNode for ReturnStat {
 impl Statement for ReturnStat {
     fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
         match self.expr {
-            Some(ref e) => match e.eval(state, f) {
-                Ok(v) => Signal::Return(v),
-                Err(e) => Signal::Error(e),
+            Some(ref e) => match eval_stat(&**e, state, f, &self.span) {
+                Ok((v, request)) => yield_after(Signal::Return(v), request, state),
+                Err(s) => s,
             },
             None => Signal::Return(Value::Null),
         }
     }
 }
 
+impl TryStat {
+    pub fn new(t: Box<dyn Statement>, e: String, c: Box<dyn Statement>) -> Self {
+        TryStat {
+            try_stat: t,
+            e_name: e,
+            catch_stat: c,
+        }
+    }
+}
+
+impl AstNode for TryStat {
+    fn print(&self) -> String {
+        "scope".to_string()
+    }
+}
+
+impl Statement for TryStat {
+    fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
+        let in_catch = match state.pop_resume() {
+            Some(Resume::Try(c)) => c,
+            _ => false,
+        };
+
+        if !in_catch {
+            let thrown = match self.try_stat.run(state, f) {
+                Signal::Throw(v) => v,
+                Signal::Error(e) => match e.catch_value() {
+                    Some(v) => v,
+                    None => return Signal::Error(e),
+                },
+                Signal::Yield(v) => {state.push_resume(Resume::Try(false)); return Signal::Yield(v);},
+                s => return s,
+            };
+
+            state.extend();
+            state.new_var(&self.e_name, thrown);
+        }
+
+        match self.catch_stat.run(state, f) {
+            Signal::Yield(v) => {state.push_resume(Resume::Try(true)); Signal::Yield(v)},
+            s => {state.reduce(); s},
+        }
+    }
+
+    fn can_yield(&self) -> bool {
+        self.try_stat.can_yield() || self.catch_stat.can_yield()
+    }
+}
+
+
+impl ThrowStat {
+    pub fn new(e: Box<dyn Expr>, s: Span) -> Self {
+        ThrowStat {
+            expr: e,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for ThrowStat {
+    fn print(&self) -> String {
+        "scope".to_string()
+    }
+}
+
+impl Statement for ThrowStat {
+    fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
+        match self.expr.eval(state, f) {
+            Ok(v) => Signal::Throw(v),
+            Err(e) => Signal::Error(e.at(&self.span)),
+        }
+    }
+}
+
+
+impl YieldStat {
+    pub fn new(e: Option<Box<dyn Expr>>, s: Span) -> Self {
+        YieldStat {
+            expr: e,
+            span: s,
+        }
+    }
+}
+
+impl AstNode for YieldStat {
+    fn print(&self) -> String {
+        "scope".to_string()
+    }
+}
+
+impl Statement for YieldStat {
+    fn run(&self, state: &mut Scope, f: &FuncMap) -> Signal {
+        // Resuming carries on after the yield.
+        if let Some(Resume::Yield) = state.pop_resume() {
+            return Signal::Done;
+        }
+
+        let val = match self.expr {
+            Some(ref e) => match e.eval(state, f) {
+                Ok(v) => v,
+                Err(e) => return Signal::Error(e.at(&self.span)),
+            },
+            None => Value::Null,
+        };
+
+        state.push_resume(Resume::Yield);
+        Signal::Yield(val)
+    }
+
+    fn can_yield(&self) -> bool {
+        true
+    }
+}
+
+
 impl ContinueStat {
     pub fn new() -> Self {
         ContinueStat {}
@@ -1,39 +1,197 @@
 use runtime::Value;
 use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
 
 pub fn mserr(code: Type) -> Result<Value, Error> {
     Err(Error::new(code))
 }
 
+pub fn mserr_at(code: Type, span: &Span) -> Result<Value, Error> {
+    Err(Error::new(code).at(span))
+}
+
+// Location in source text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, col: usize) -> Self {
+        Span {
+            line,
+            col,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+// Script function an error propagated out of, and where it was called from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub package: String,
+    pub function: String,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Closure names already include their package.
+        if self.function.starts_with('<') {
+            write!(f, "in {}", self.function)?;
+        } else {
+            write!(f, "in {}::{}", self.package, self.function)?;
+        }
+
+        match (&self.file, &self.span) {
+            (Some(fl), Some(s)) => write!(f, ", called at {}:{}", fl, s),
+            (Some(fl), None)    => write!(f, ", called in {}", fl),
+            (None, Some(s))     => write!(f, ", called at {}", s),
+            (None, None)        => Ok(()),
+        }
+    }
+}
+
 pub struct Error {
     err_code: Type,
-    // line
-    // Function name?
-    // Package?
+    file: Option<String>,
+    span: Option<Span>,
+    trace: Vec<Frame>,
 }
 
 impl Error {
     pub fn new(code: Type) -> Self {
         Error {
             err_code: code,
+            file: None,
+            span: None,
+            trace: Vec::new(),
         }
     }
 
-    pub fn to_string(&self) -> String {
+    // Sets the location of the error, if it isn't already known.
+    // Once the error has left a function, sets where that function was called instead.
+    pub fn at(mut self, span: &Span) -> Self {
+        let loc = match self.trace.last_mut() {
+            Some(frame) => &mut frame.span,
+            None => &mut self.span,
+        };
+
+        if loc.is_none() {
+            *loc = Some(*span);
+        }
+        self
+    }
+
+    // Sets the file the error occurred in, if it isn't already known.
+    // Once the error has left a function, sets the file of the call site instead.
+    pub fn in_file(mut self, file: &str) -> Self {
+        let loc = match self.trace.last_mut() {
+            Some(frame) => &mut frame.file,
+            None => &mut self.file,
+        };
+
+        if loc.is_none() {
+            *loc = Some(file.to_string());
+        }
+        self
+    }
+
+    // Records that the error propagated out of a script function.
+    pub fn in_function(mut self, package: &str, function: &str) -> Self {
+        self.trace.push(Frame {
+            package: package.to_string(),
+            function: function.to_string(),
+            file: None,
+            span: None,
+        });
+        self
+    }
+
+    pub fn get_type(&self) -> &Type {
+        &self.err_code
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    // Functions the error propagated through, innermost first.
+    pub fn trace(&self) -> &[Frame] {
+        &self.trace
+    }
+
+    // Value thrown by a script, if this error is an exception.
+    pub fn exception(&self) -> Option<&Value> {
+        match self.err_code {
+            Type::Exception(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    // Value passed to a script catch block, if the error can be caught.
+    // Exceptions give the thrown value, runtime and package errors
+    // give an object with kind, message and payload fields.
+    pub fn catch_value(&self) -> Option<Value> {
+        use Type::*;
+        let (kind, payload) = match self.err_code {
+            Exception(ref v)    => return Some(v.clone()),
+            RunTime(ref ec)     => (format!("{:?}", ec), Value::Null),
+            Package(ref pe)     => ("Package".to_string(), pe.to_value().unwrap_or(Value::Null)),
+            CompileTime(_)      |
+            Warning(_)          |
+            Critical(_)         => return None,
+        };
+
+        let mut obj = HashMap::new();
+        obj.insert("kind".to_string(), Value::Str(Rc::new(RefCell::new(kind))));
+        obj.insert("message".to_string(), Value::Str(Rc::new(RefCell::new(self.message()))));
+        obj.insert("payload".to_string(), payload);
+        Some(Value::Obj(Rc::new(RefCell::new(obj)), Rc::new(RefCell::new(None))))
+    }
+
+    // Description of the error, without location.
+    pub fn message(&self) -> String {
         use Type::*;
         match self.err_code {
             Exception(ref v)    => format!("Exception occurred: {}", v),
-            CompileTime(ref ec) => format!("Compile error: {:?}", ec),
+            CompileTime(ref ec) => format!("Compile error: {}", ec),
+            Warning(ref ec)     => format!("Compile warning: {}", ec),
             RunTime(ref ec)     => format!("Runtime error: {:?}", ec),
             Critical(ref ec)    => format!("Internal error: {:?}", ec),
-            Package(ref pe)     => format!("{}", pe.to_string())
+            Package(ref pe)     => pe.to_string(),
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        let msg = self.message();
+
+        match (&self.file, &self.span) {
+            (Some(file), Some(s))   => write!(f, "{}:{}: {}", file, s, msg)?,
+            (Some(file), None)      => write!(f, "{}: {}", file, msg)?,
+            (None, Some(s))         => write!(f, "{}: {}", s, msg)?,
+            (None, None)            => write!(f, "{}", msg)?,
+        }
+
+        for frame in &self.trace {
+            write!(f, "\n    {}", frame)?;
+        }
+        Ok(())
     }
 }
 
@@ -46,6 +204,8 @@ impl fmt::Debug for Error {
 pub enum Type {
     Exception(Value),
     CompileTime(CompileCode),
+    // Problems found by the compiler that don't stop a package from being built
+    Warning(CompileCode),
     RunTime(RunCode),
     Critical(CriticalCode),
     Package(Box<dyn CustomError>)
@@ -56,6 +216,88 @@ pub enum CompileCode {
     Error,
     InvalidFile,
     PackageNotFound,
+
+    // Syntax Errors
+    UnexpectedToken(Box<UnexpectedToken>),
+    InvalidCharacter(char),
+    UnterminatedString,
+    UnterminatedComment,
+    UnknownVariant(String),
+    UnterminatedInterpolation,
+    InvalidEscape(String),
+    InvalidNumber(String),
+    NumberTooLarge(String),
+    YieldOutsideFunction,
+
+    // Warnings
+    NonExhaustiveMatch(Box<NonExhaustiveMatch>),
+}
+
+impl fmt::Display for CompileCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::CompileCode::*;
+        match *self {
+            UnexpectedToken(ref u) => write!(f, "{}", u),
+            InvalidCharacter(c) => write!(f, "invalid character `{}`", c),
+            UnterminatedString => write!(f, "unterminated string"),
+            UnterminatedComment => write!(f, "unterminated comment"),
+            UnknownVariant(ref v) => write!(f, "unknown package or enum variant `{}`", v),
+            UnterminatedInterpolation => write!(f, "unterminated `{{` in string"),
+            InvalidEscape(ref e) => write!(f, "invalid escape `{}` in string", e),
+            InvalidNumber(ref n) => write!(f, "invalid number `{}`", n),
+            NumberTooLarge(ref n) => write!(f, "number `{}` is too large", n),
+            YieldOutsideFunction => write!(f, "`yield` outside of a function"),
+            NonExhaustiveMatch(ref m) => write!(f, "{}", m),
+            ref ec => write!(f, "{:?}", ec),
+        }
+    }
+}
+
+// Token the parser could not handle, and what it would have accepted there.
+#[derive(Debug)]
+pub struct UnexpectedToken {
+    pub found: String,
+    pub expected: Vec<String>,
+    pub function: Option<String>,
+}
+
+impl fmt::Display for UnexpectedToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unexpected {}", self.found)?;
+
+        if let Some((last, rest)) = self.expected.split_last() {
+            if rest.is_empty() {
+                write!(f, ", expected {}", last)?;
+            } else {
+                write!(f, ", expected {} or {}", rest.join(", "), last)?;
+            }
+        }
+
+        match self.function {
+            Some(ref n) => write!(f, " in function `{}`", n),
+            None => Ok(()),
+        }
+    }
+}
+
+// Match on an enum without an else, that has no case for some of its variants.
+#[derive(Debug)]
+pub struct NonExhaustiveMatch {
+    pub name: String,
+    pub missing: Vec<String>,
+    pub function: Option<String>,
+}
+
+impl fmt::Display for NonExhaustiveMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let missing = self.missing.iter().map(|v| format!("`{}::{}`", self.name, v)).collect::<Vec<_>>();
+        write!(f, "match on `{}` has no case for {}", self.name, missing.join(", "))?;
+
+        match self.function {
+            Some(ref n) => write!(f, " in function `{}`", n),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -72,6 +314,8 @@ pub enum RunCode {
     WrongNumberOfArguments,
     CannotContinue,
     CannotBreak,
+    CannotYield,
+    GeneratorRunning,
 
     // Expr Errors
     OutOfBounds,
@@ -80,6 +324,13 @@ pub enum RunCode {
     InvalidNegative,
     ValueNotHashable,
     InvalidRange,
+    MissingElement,
+    TooManyElements,
+    UnknownField,
+    MissingField,
+    StructNotFound,
+    EnumNotFound,
+    VariantNotFound,
 
     // Core Function Errors
     CoreFunctionNotFound,
@@ -88,6 +339,7 @@ pub enum RunCode {
     CoreArgumentTypeError,
     CoreParseError,
     CoreAccessError,
+    CyclicPrototype,
 }
 
 #[derive(Debug)]
@@ -98,4 +350,9 @@ pub enum CriticalCode {
 
 pub trait CustomError {
     fn to_string(&self) -> String;
+
+    // Structured data given to scripts that catch this error.
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
 }
@@ -6,18 +6,18 @@ mod runtime;
 mod parser;
 mod error;
 
-pub use ast::{ScriptPackage, Script, ScriptExpr};
-pub use runtime::{Value, VType, Signal, ExprRes, FuncMap, Scope, Callable, PackageRoot};
+pub use ast::{ScriptPackage, Script, ScriptExpr, Coroutine, Resumed};
+pub use runtime::{Value, VType, Signal, ExprRes, FuncMap, Scope, Callable, Package, PackageRoot, NativeIter};
 pub use error::*;
-use parser::{tokenise, parse_package, parse_snippet, parse_expr_snippet, Token};
+use parser::{tokenise, parse_package, parse_snippet, parse_expr_snippet, Token, SpannedToken};
 
 use std::fs::File;
 use std::io::{BufReader, Read};
 
-pub fn package_from_file(file_name: &str) -> Result<ScriptPackage, Error> {
+pub fn package_from_file(file_name: &str) -> Result<ScriptPackage, Vec<Error>> {
     let file = match File::open(file_name) {
         Ok(f) => f,
-        Err(_) => return Err(Error::new(Type::CompileTime(CompileCode::InvalidFile))),
+        Err(_) => return Err(vec![Error::new(Type::CompileTime(CompileCode::InvalidFile))]),
     };
 
     let mut buf_reader = BufReader::new(file);
@@ -25,7 +25,7 @@ pub fn package_from_file(file_name: &str) -> Result<ScriptPackage, Error> {
 
     buf_reader.read_to_string(&mut contents).unwrap();
 
-    let tokens = tokenise(&contents)?;
+    let tokens = tokenise(&contents).map_err(|e| vec![e.in_file(file_name)])?;
 
     parse_package(&tokens, file_name)
 }
@@ -39,7 +39,8 @@ pub fn script_from_text(imports: &[(String,String)], script: &str) -> Result<Scr
 
 pub fn expr_from_text(imports: &[(String,String)], script: &str) -> Result<ScriptExpr, Error> {
     let mut tokens = tokenise(script)?;
-    tokens.push(Token::SemiColon);
+    let end = tokens.last().map_or(Span::new(1, 1), |t| t.span);
+    tokens.push(SpannedToken::new(Token::SemiColon, end));
 
     parse_expr_snippet(&tokens, imports)
 }
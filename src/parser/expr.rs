@@ -1,105 +1,101 @@
-// LALR Expression parser
+// Pratt (precedence climbing) expression parser
 
-use super::Token;
-use parser::parser::{get_package_ref, p_expr};
+use super::{Token, SpannedToken};
+use parser::parser::{get_package_ref, is_package_ref, note_enum_ref, p_expr, p_method, p_if_expr, p_match_expr, struct_literals_allowed, expect, expected};
 
 use ast::*;
+use error::Span;
 
-use nom::{IResult, Needed, Err, ErrorKind, Context};
-
-type ExprRes<'a> = IResult<&'a [Token], Box<dyn Expr>>;
-
-macro_rules! op_match {
-    ($input:ident, $mat:ident, $first:expr, $ast:path) => {
-        match $mat(&$input[1..]) {
-            Ok((ir, expr)) => {
-                if ir.len() < 1 {
-                    Err(Err::Incomplete(Needed::Size(1)))
-                } else { match ir[0] {
-                    Token::SemiColon    |
-                    Token::RPar         |
-                    Token::LBrac        |
-                    Token::RBrac        |
-                    Token::RSq          |
-                    Token::AsnPlus      |
-                    Token::AsnMinus     |
-                    Token::AsnTimes     |
-                    Token::AsnDivide    |
-                    Token::AsnModulo    |
-                    Token::AsnOr        |
-                    Token::AsnXor       |
-                    Token::AsnAnd       |
-                    Token::Assign       |
-                    Token::DoubleDot    |
-                    Token::Colon        |
-                    Token::Comma => Ok((ir, Box::new($ast($first,expr)))),
-                    _ => p_op(Box::new($ast($first,expr)), ir),
-                }}
-            },
-            e => e,
-        }
-    };
+use nom::{IResult, Needed, Err};
+
+type ExprRes<'a> = IResult<&'a [SpannedToken], Box<dyn Expr>>;
+
+type BinaryCons = fn(Box<dyn Expr>, Box<dyn Expr>, Span) -> Box<dyn Expr>;
+
+// Binary operators, from loosest to tightest binding:
+//
+//  1   ||                  short-circuiting or
+//  2   &&                  short-circuiting and
+//  3   |                   bitwise/logical or
+//  4   ^                   bitwise/logical xor
+//  5   &                   bitwise/logical and
+//  6   == != === !==       equality
+//  7   < <= > >=           relational
+//  8   + -                 additive
+//  9   * / %               multiplicative
+//
+// All binary operators are left-associative. Unary ! and - bind tighter than
+// any binary operator, and calls, indexing and field access tighter still.
+fn binary_op(t: &Token) -> Option<(u32, BinaryCons)> {
+    match *t {
+        Token::LogicOr  => Some((1, |l, r, s| Box::new(LogicOrExpr::new(l, r, s)))),
+        Token::LogicAnd => Some((2, |l, r, s| Box::new(LogicAndExpr::new(l, r, s)))),
+        Token::Or       => Some((3, |l, r, s| Box::new(OrExpr::new(l, r, s)))),
+        Token::Xor      => Some((4, |l, r, s| Box::new(XorExpr::new(l, r, s)))),
+        Token::And      => Some((5, |l, r, s| Box::new(AndExpr::new(l, r, s)))),
+        Token::Equal    => Some((6, |l, r, s| Box::new(EqExpr::new(l, r, s)))),
+        Token::NEqual   => Some((6, |l, r, s| Box::new(NEqExpr::new(l, r, s)))),
+        Token::TrueEq   => Some((6, |l, r, s| Box::new(TrueEqExpr::new(l, r, s)))),
+        Token::TrueNEq  => Some((6, |l, r, s| Box::new(TrueNEqExpr::new(l, r, s)))),
+        Token::LThan    => Some((RELATIONAL_PREC, |l, r, s| Box::new(LThanExpr::new(l, r, s)))),
+        Token::LEq      => Some((RELATIONAL_PREC, |l, r, s| Box::new(LEqExpr::new(l, r, s)))),
+        Token::GThan    => Some((RELATIONAL_PREC, |l, r, s| Box::new(GThanExpr::new(l, r, s)))),
+        Token::GEq      => Some((RELATIONAL_PREC, |l, r, s| Box::new(GEqExpr::new(l, r, s)))),
+        Token::Plus     => Some((8, |l, r, s| Box::new(AddExpr::new(l, r, s)))),
+        Token::Minus    => Some((8, |l, r, s| Box::new(SubExpr::new(l, r, s)))),
+        Token::Times    => Some((9, |l, r, s| Box::new(MulExpr::new(l, r, s)))),
+        Token::Divide   => Some((9, |l, r, s| Box::new(DivExpr::new(l, r, s)))),
+        Token::Modulo   => Some((9, |l, r, s| Box::new(ModExpr::new(l, r, s)))),
+        _ => None,
+    }
 }
 
-pub fn p_expr_lalr<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 2 {
-        Err(Err::Incomplete(Needed::Size(2)))
-    } else { match p_unary(&input) {
-        Ok((ir,expr)) => {
-            if ir.len() < 1 {
-                Err(Err::Incomplete(Needed::Size(1)))
-            } else { match ir[0] {
-                Token::SemiColon    |
-                Token::RPar         |
-                Token::LBrac        |
-                Token::RBrac        |
-                Token::RSq          |
-                Token::AsnPlus      |
-                Token::AsnMinus     |
-                Token::AsnTimes     |
-                Token::AsnDivide    |
-                Token::AsnModulo    |
-                Token::AsnOr        |
-                Token::AsnXor       |
-                Token::AsnAnd       |
-                Token::Assign       |
-                Token::DoubleDot    |
-                Token::Colon        |
-                Token::Comma => Ok((ir,expr)),
-                _ => p_op(expr, ir),
-            }}
+// Precedence of `<` and the other relational operators, which pair elements bind tighter than.
+const RELATIONAL_PREC: u32 = 7;
+
+// The conditional operator binds loosest of all, and is right-associative:
+// a ? b : c ? d : e is a ? b : (c ? d : e).
+pub fn p_expr_pratt<'a>(input: &'a [SpannedToken]) -> ExprRes<'a> {
+    let (rest, cond) = p_binary(input, 0)?;
+
+    match rest.first() {
+        Some(t) if t.token == Token::Question => {
+            let span = t.span;
+            let (rest, then_expr) = p_expr(&rest[1..])?;
+            match rest.first() {
+                Some(t) if t.token == Token::Colon => {
+                    let (rest, else_expr) = p_expr(&rest[1..])?;
+                    Ok((rest, Box::new(CondExpr::new(cond, then_expr, else_expr, span))))
+                },
+                Some(_) => expected(rest, 100, "`:`"),
+                None => Err(Err::Incomplete(Needed::Size(1))),
+            }
         },
-        e => e,
-    }}
+        _ => Ok((rest, cond)),
+    }
 }
 
-fn p_op<'a>(first: Box<dyn Expr>, input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 2 {
-        Err(Err::Incomplete(Needed::Size(2)))
-    } else { match input[0] {
-        // Token::LSq...
-        Token::Times => op_match!(input, p_unary, first, MulExpr::new),
-        Token::Divide => op_match!(input, p_unary, first, DivExpr::new),
-        Token::Modulo => op_match!(input, p_unary, first, ModExpr::new),
-        Token::Plus => op_match!(input, p_mul_div, first, AddExpr::new),
-        Token::Minus => op_match!(input, p_mul_div, first, SubExpr::new),
-        Token::GThan => op_match!(input, p_add_sub, first, GThanExpr::new),
-        Token::GEq => op_match!(input, p_add_sub, first, GEqExpr::new),
-        Token::LThan => op_match!(input, p_add_sub, first, LThanExpr::new),
-        Token::LEq => op_match!(input, p_add_sub, first, LEqExpr::new),
-        Token::Equal => op_match!(input, p_relational, first, EqExpr::new),
-        Token::NEqual => op_match!(input, p_relational, first, NEqExpr::new),
-        Token::TrueEq => op_match!(input, p_relational, first, TrueEqExpr::new),
-        Token::TrueNEq => op_match!(input, p_relational, first, TrueNEqExpr::new),
-        Token::And => op_match!(input, p_equality, first, AndExpr::new),
-        Token::Xor => op_match!(input, p_equality, first, XorExpr::new),
-        Token::Or => op_match!(input, p_equality, first, OrExpr::new),
-        _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(101)))),
-    }}
+// Parses an expression made of operators that bind tighter than min_prec.
+fn p_binary<'a>(input: &'a [SpannedToken], min_prec: u32) -> ExprRes<'a> {
+    let (mut rest, mut expr) = p_unary(input)?;
+
+    while let Some(op) = rest.first() {
+        match binary_op(&op.token) {
+            Some((prec, cons)) if prec > min_prec => {
+                // Operators of the same precedence are left for this loop, making them left-associative.
+                let (r, right) = p_binary(&rest[1..], prec)?;
+                expr = cons(expr, right, op.span);
+                rest = r;
+            },
+            _ => break,
+        }
+    }
+
+    Ok((rest, expr))
 }
 
 // Assuming input[0] has already been matched as LPar or comma or LSq
-fn p_expr_list<'a>(input: &'a [Token], mut exprs: Vec<Box<dyn Expr>>, term: Token) -> IResult<&'a [Token], Vec<Box<dyn Expr>>> {
+fn p_expr_list<'a>(input: &'a [SpannedToken], mut exprs: Vec<Box<dyn Expr>>, term: Token) -> IResult<&'a [SpannedToken], Vec<Box<dyn Expr>>> {
     if input.len() < 3 {
         Err(Err::Incomplete(Needed::Size(3)))
     } else { match p_expr(&input[1..]) {
@@ -107,10 +103,13 @@ fn p_expr_list<'a>(input: &'a [Token], mut exprs: Vec<Box<dyn Expr>>, term: Toke
             exprs.push(expr);
             if ir.len() < 2 {
                 Err(Err::Incomplete(Needed::Size(2)))
-            } else { match ir[0] {
+            } else { match ir[0].token {
                 Token::Comma => p_expr_list(ir, exprs, term),
-                ref t if t == &term => Ok((&ir[1..], exprs)),
-                _ => Err(Err::Error(Context::Code(ir, ErrorKind::Custom(101)))),
+                ref t if *t == term => Ok((&ir[1..], exprs)),
+                _ => {
+                    expect(ir, "`,`");
+                    expected(ir, 101, &format!("`{}`", term))
+                },
             }}
         },
         Err(e) => Err(e),
@@ -118,40 +117,41 @@ fn p_expr_list<'a>(input: &'a [Token], mut exprs: Vec<Box<dyn Expr>>, term: Toke
 }
 
 // Assuming input[0] has already been matched as Dot
-fn p_access<'a>(input: &'a [Token], first: Box<dyn Expr>) -> ExprRes<'a> {
+fn p_access<'a>(input: &'a [SpannedToken], first: Box<dyn Expr>) -> ExprRes<'a> {
     if input.len() < 3 {
         Err(Err::Incomplete(Needed::Size(3)))
-    } else { match input[1] {
-        Token::Id(ref n) => p_post_op(&input[2..], Box::new(AccessExpr::new(first,n))),
-        _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+    } else { match input[1].token {
+        Token::Id(ref n) => p_post_op(&input[2..], Box::new(AccessExpr::new(first,n,input[0].span))),
+        _ => expected(&input[1..], 100, "field name"),
     }}
 }
 
 // Assuming input[0] has already been matched as Arrow
-fn p_core_func<'a>(input: &'a [Token], first: Box<dyn Expr>) -> ExprRes<'a> {
+fn p_core_func<'a>(input: &'a [SpannedToken], first: Box<dyn Expr>) -> ExprRes<'a> {
     if input.len() < 5 {
         Err(Err::Incomplete(Needed::Size(5)))
-    } else { match (&input[1], &input[2], &input[3]) {
-        (&Token::Id(ref n), &Token::LPar, &Token::RPar) => p_post_op(&input[4..],Box::new(CoreFuncCall::new(n,first,Vec::new()))),
+    } else { match (&input[1].token, &input[2].token, &input[3].token) {
+        (&Token::Id(ref n), &Token::LPar, &Token::RPar) => p_post_op(&input[4..],Box::new(CoreFuncCall::new(n,first,Vec::new(),input[0].span))),
         (&Token::Id(ref n), &Token::LPar, _) => match p_expr_list(&input[2..],Vec::new(),Token::RPar) {
-            Ok((ir,args)) => p_post_op(ir,Box::new(CoreFuncCall::new(n,first,args))),
+            Ok((ir,args)) => p_post_op(ir,Box::new(CoreFuncCall::new(n,first,args,input[0].span))),
             Err(e) => Err(e),
         },
-        (_,_,_) => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+        (&Token::Id(_), _, _) => expected(&input[2..], 100, "`(`"),
+        (_,_,_) => expected(&input[1..], 100, "core function name"),
     }}
 }
 
-fn p_post_op<'a>(input: &'a [Token], first: Box<dyn Expr>) -> ExprRes<'a> {
+fn p_post_op<'a>(input: &'a [SpannedToken], first: Box<dyn Expr>) -> ExprRes<'a> {
     if input.len() < 1 {
         Err(Err::Incomplete(Needed::Size(1)))
-    } else { match input[0] {
+    } else { match input[0].token {
         Token::LPar => {
             if input.len() < 2 {
                 Err(Err::Incomplete(Needed::Size(2)))
-            } else { match input[1] {
-                Token::RPar => p_post_op(&input[2..], Box::new(FuncCall::new(first, Vec::new()))),
+            } else { match input[1].token {
+                Token::RPar => p_post_op(&input[2..], Box::new(FuncCall::new(first, Vec::new(), input[0].span))),
                 _ => match p_expr_list(&input[0..], Vec::new(), Token::RPar) {
-                    Ok((ir, args)) => p_post_op(ir, Box::new(FuncCall::new(first, args))),
+                    Ok((ir, args)) => p_post_op(ir, Box::new(FuncCall::new(first, args, input[0].span))),
                     Err(e) => Err(e),
                 },
             }}
@@ -162,9 +162,9 @@ fn p_post_op<'a>(input: &'a [Token], first: Box<dyn Expr>) -> ExprRes<'a> {
             } else { match p_expr(&input[1..]) {
                 Ok((ir,expr)) => if ir.len() < 2 {
                     Err(Err::Incomplete(Needed::Size(2)))
-                } else { match ir[0] {
-                    Token::RSq => p_post_op(&ir[1..],Box::new(IndexExpr::new(first,expr))),
-                    _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+                } else { match ir[0].token {
+                    Token::RSq => p_post_op(&ir[1..],Box::new(IndexExpr::new(first,expr,input[0].span))),
+                    _ => expected(ir, 100, "`]`"),
                 }},
                 e => e,
             }}
@@ -175,32 +175,79 @@ fn p_post_op<'a>(input: &'a [Token], first: Box<dyn Expr>) -> ExprRes<'a> {
     }}
 }
 
-fn p_object<'a>(input: &'a[Token], mut items: Vec<(String, Box<dyn Expr>)>) -> ExprRes<'a> {
-    fn p_obj_pair<'a>(input: &'a[Token], split: Token) -> IResult<&'a [Token], (String, Box<dyn Expr>)> {
+// Struct literal `Name{field: value}`, which builds the struct from an object of the fields.
+fn p_struct_or_post_op<'a>(input: &'a[SpannedToken], package: String, name: &str) -> ExprRes<'a> {
+    match input.first() {
+        Some(t) if t.token == Token::LBrac && struct_literals_allowed() => match p_object(&input[1..], Vec::new()) {
+            Ok((ir, obj)) => p_post_op(ir, Box::new(StructExpr::new(package, name, obj, t.span))),
+            Err(e) => Err(e),
+        },
+        _ => p_post_op(input, Box::new(ValExpr::QualId(package, name.to_string()))),
+    }
+}
+
+// Enum variant, as `Enum::Variant` or `pack::Enum::Variant`, followed by its payload if it has one.
+fn p_enum_variant<'a>(input: &'a[SpannedToken], package: String, name: &str, variant: &str, span: Span) -> ExprRes<'a> {
+    let (rest, args) = match (input.first().map(|t| &t.token), input.get(1).map(|t| &t.token)) {
+        (Some(Token::LPar), Some(Token::RPar)) => (&input[2..], Vec::new()),
+        (Some(Token::LPar), _) => p_expr_list(input, Vec::new(), Token::RPar)?,
+        _ => (input, Vec::new()),
+    };
+
+    p_post_op(rest, Box::new(VariantExpr::new(package, name, variant, args, span)))
+}
+
+// Rest of an interpolated string, from an embedded expression onwards.
+fn p_interp<'a>(input: &'a[SpannedToken], mut parts: Vec<Box<dyn Expr>>, span: Span) -> ExprRes<'a> {
+    let (rest, expr) = p_expr(input)?;
+    parts.push(expr);
+
+    match rest.first().map(|t| &t.token) {
+        Some(Token::StrMid(ref s)) => p_interp(&rest[1..], text_part(s, parts), span),
+        Some(Token::StrEnd(ref s)) => p_post_op(&rest[1..], Box::new(InterpExpr::new(text_part(s, parts), span))),
+        Some(_) => expected(rest, 100, "`}`"),
+        None => Err(Err::Incomplete(Needed::Size(1))),
+    }
+}
+
+fn text_part(text: &str, mut parts: Vec<Box<dyn Expr>>) -> Vec<Box<dyn Expr>> {
+    if !text.is_empty() {
+        parts.push(Box::new(ValExpr::Text(text.to_string())));
+    }
+    parts
+}
+
+fn p_object<'a>(input: &'a[SpannedToken], mut items: Vec<(String, Box<dyn Expr>)>) -> ExprRes<'a> {
+    fn p_obj_pair<'a>(input: &'a[SpannedToken], split: Token) -> IResult<&'a [SpannedToken], (String, Box<dyn Expr>)> {
         if input.len() < 3 {
             Err(Err::Incomplete(Needed::Size(3)))
-        } else { match (&input[0],input[1] == split) {
+        } else { match (&input[0].token,input[1] == split) {
             (&Token::Id(ref n), true) => match p_expr(&input[2..]) {
                 Ok((ir,expr)) => Ok((ir,(n.clone(),expr))),
                 Err(e) => Err(e),
             },
-            (_,_) => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+            (&Token::Id(_), false) => expected(&input[1..], 100, &format!("`{}`", split)),
+            (&Token::Func, _) => p_method(input, None),
+            (_,_) => expected(input, 100, "field name"),
         }}
     }
 
     if input.len() < 2 {
         Err(Err::Incomplete(Needed::Size(2)))
-    } else { match input[0] {
+    } else { match input[0].token {
         Token::RBrac => Ok((&input[1..], Box::new(ValExpr::Obj(items)))),
         _ => match p_obj_pair(&input[0..], Token::Colon) {
             Ok((ir,res)) => {
                 items.push(res);
                 if ir.len() < 2 {
                     Err(Err::Incomplete(Needed::Size(2)))
-                } else { match ir[0] {
+                } else { match ir[0].token {
                     Token::RBrac => Ok((&ir[1..], Box::new(ValExpr::Obj(items)))),
                     Token::Comma => p_object(&ir[1..], items),
-                    _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+                    _ => {
+                        expect(ir, "`,`");
+                        expected(ir, 100, "`}`")
+                    },
                 }}
             },
             Err(e) => Err(e),
@@ -208,8 +255,8 @@ fn p_object<'a>(input: &'a[Token], mut items: Vec<(String, Box<dyn Expr>)>) -> E
     }}
 }
 
-fn p_hashmap<'a>(input: &'a[Token], mut items: Vec<(Box<dyn Expr>, Box<dyn Expr>)>) -> ExprRes<'a> {
-    fn p_map_pair<'a>(input: &'a[Token], split: Token) -> IResult<&'a [Token], (Box<dyn Expr>, Box<dyn Expr>)> {
+fn p_hashmap<'a>(input: &'a[SpannedToken], mut items: Vec<(Box<dyn Expr>, Box<dyn Expr>)>) -> ExprRes<'a> {
+    fn p_map_pair<'a>(input: &'a[SpannedToken], split: Token) -> IResult<&'a [SpannedToken], (Box<dyn Expr>, Box<dyn Expr>)> {
         if input.len() < 5 {
             Err(Err::Incomplete(Needed::Size(5)))
         } else if input[0] == Token::LSq { match p_expr(&input[1..]) {
@@ -217,28 +264,33 @@ fn p_hashmap<'a>(input: &'a[Token], mut items: Vec<(Box<dyn Expr>, Box<dyn Expr>
             Ok((ir,key)) => if ir[0] == Token::RSq && ir[1] == split { match p_expr(&ir[2..]) {
                 Ok((ir,val)) => Ok((ir, (key,val))),
                 Err(e) => Err(e),
-            }} else {
-                Err(Err::Error(Context::Code(input, ErrorKind::Custom(100))))
+            }} else if ir[0] == Token::RSq {
+                expected(&ir[1..], 100, &format!("`{}`", split))
+            } else {
+                expected(ir, 100, "`]`")
             },
             Err(e) => Err(e),
         }} else {
-            Err(Err::Error(Context::Code(input, ErrorKind::Custom(100))))
+            expected(input, 100, "`[`")
         }
     }
 
     if input.len() < 4 {
         Err(Err::Incomplete(Needed::Size(4)))
-    } else { match (&input[1],&input[2])  {
+    } else { match (&input[1].token,&input[2].token)  {
         (&Token::RSq,&Token::RBrac) => Ok((&input[3..], Box::new(ValExpr::Map(items)))),
         _ => match p_map_pair(&input[0..], Token::Assign) {
             Ok((ir,res)) => {
                 items.push(res);
                 if ir.len() < 2 {
                     Err(Err::Incomplete(Needed::Size(2)))
-                } else { match ir[0] {
+                } else { match ir[0].token {
                     Token::RBrac => Ok((&ir[1..], Box::new(ValExpr::Map(items)))),
                     Token::Comma => p_hashmap(&ir[1..], items),
-                    _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+                    _ => {
+                        expect(ir, "`,`");
+                        expected(ir, 100, "`}`")
+                    },
                 }}
             }
             Err(e) => Err(e),
@@ -246,77 +298,86 @@ fn p_hashmap<'a>(input: &'a[Token], mut items: Vec<(Box<dyn Expr>, Box<dyn Expr>
     }}
 }
 
-fn p_range<'a>(input: &'a[Token]) -> ExprRes<'a> {
+fn p_range<'a>(input: &'a[SpannedToken]) -> ExprRes<'a> {
     if input.len() < 4 {
         Err(Err::Incomplete(Needed::Size(4)))
     } else { match p_expr(input) {
-        Ok((ir,lo)) => match ir[0] {
+        Ok((ir,lo)) => match ir[0].token {
             Token::DoubleDot => {
                 if input.len() < 2 {
                     Err(Err::Incomplete(Needed::Size(2)))
                 } else { match p_expr(&ir[1..]) {
-                    Ok((ir,mid)) => match ir[0] {
-                        Token::RSq => Ok((&ir[1..], Box::new(RangeExpr::new(lo, None, mid)))),
+                    Ok((ir,mid)) => match ir[0].token {
+                        Token::RSq => Ok((&ir[1..], Box::new(RangeExpr::new(lo, None, mid, input[0].span)))),
                         Token::DoubleDot => {
                             if input.len() < 2 {
                                 Err(Err::Incomplete(Needed::Size(2)))
                             } else { match p_expr(&ir[1..]) {
-                                Ok((ir,hi)) => match ir[0] {
-                                    Token::RSq => Ok((&ir[1..], Box::new(RangeExpr::new(lo, Some(mid), hi)))),
-                                    _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+                                Ok((ir,hi)) => match ir[0].token {
+                                    Token::RSq => Ok((&ir[1..], Box::new(RangeExpr::new(lo, Some(mid), hi, input[0].span)))),
+                                    _ => expected(ir, 100, "`]`"),
                                 },
                                 Err(e) => Err(e),
                             }}
                         },
-                        _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+                        _ => {
+                            expect(ir, "`..`");
+                            expected(ir, 100, "`]`")
+                        },
                     },
                     Err(e) => Err(e),
                 }}
             },
-            _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+            _ => expected(ir, 100, "`..`"),
         },
         Err(e) => Err(e),
     }}
 }
 
-/*fn p_pair<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 4 {
-        Err(Err::Incomplete(Needed::Size(4)))
-    } else { match p_expr(&input[0..]) {
-        Ok((ir,e)) => {
-            if ir.len() < 3 {
-                Err(Err::Incomplete(Needed::Size(4)))
-            } else { match ir[]
+// Assuming input[0] has already been matched as LThan.
+// The elements can't contain comparisons without parentheses, as they would swallow the closing `>`.
+fn p_pair<'a>(input: &'a [SpannedToken]) -> ExprRes<'a> {
+    let (ir, left) = p_binary(input, RELATIONAL_PREC)?;
+    let ir = match ir.first() {
+        Some(t) if t.token == Token::Comma => &ir[1..],
+        Some(_) => return expected(ir, 100, "`,`"),
+        None => return Err(Err::Incomplete(Needed::Size(1))),
+    };
 
-            }}
-        },
-        Err(e) => Err(e),
-    }}
-}*/
+    let (ir, right) = p_binary(ir, RELATIONAL_PREC)?;
+    match ir.first() {
+        Some(t) if t.token == Token::GThan => p_post_op(&ir[1..], Box::new(ValExpr::Pair(left, right))),
+        Some(_) => expected(ir, 100, "`>`"),
+        None => Err(Err::Incomplete(Needed::Size(1))),
+    }
+}
 
-fn p_atom<'a>(input: &'a [Token]) -> ExprRes<'a> {
+fn p_atom<'a>(input: &'a [SpannedToken]) -> ExprRes<'a> {
     if input.len() < 2 {
         Err(Err::Incomplete(Needed::Size(2)))
-    } else { match input[0] {
+    } else { match input[0].token {
         Token::IntLit(i) => p_post_op(&input[1..], Box::new(ValExpr::Int(i))),
         Token::FloatLit(f) => p_post_op(&input[1..], Box::new(ValExpr::Float(f))),
         Token::StrLit(ref s) => p_post_op(&input[1..], Box::new(ValExpr::Text(s.clone()))),
+        Token::StrStart(ref s) => p_interp(&input[1..], text_part(s, Vec::new()), input[0].span),
         Token::True => Ok((&input[1..], Box::new(ValExpr::Bool(true)))),    //TODO: can you post-op true/false?
         Token::False => Ok((&input[1..], Box::new(ValExpr::Bool(false)))),
         Token::Null => Ok((&input[1..], Box::new(ValExpr::Null))),
+        Token::If => p_if_expr(input),
+        Token::Match => p_match_expr(input),
         Token::LPar => match p_expr(&input[1..]) {
             Ok((ir,expr)) => {
                 if ir.len() < 2 {
                     Err(Err::Incomplete(Needed::Size(2)))
-                } else { match ir[0] {
+                } else { match ir[0].token {
                     Token::RPar => p_post_op(&ir[1..], expr),
-                    _ => Err(Err::Error(Context::Code(ir, ErrorKind::Custom(100)))),
+                    _ => expected(ir, 100, "`)`"),
                 }}
             },
             e => e,
         },
-        //Token::LThan => p_pair(&input[1..]),
-        Token::LSq => match input[1] {
+        Token::LThan => p_pair(&input[1..]),
+        Token::LSq => match input[1].token {
             Token::RSq => p_post_op(&input[2..], Box::new(ValExpr::List(Vec::new()))), // TODO: check size
             _ => match p_expr_list(&input, Vec::new(), Token::RSq) {
                 Ok((ir,exprs)) => p_post_op(ir, Box::new(ValExpr::List(exprs))),
@@ -326,131 +387,115 @@ fn p_atom<'a>(input: &'a [Token]) -> ExprRes<'a> {
                 },
             }
         },
-        Token::LBrac => match input[1] {
+        Token::LBrac => match input[1].token {
             Token::LSq => p_hashmap(&input[1..], Vec::new()),
             _ => p_object(&input[1..], Vec::new()),
         },
-        Token::Id(ref n) => match input[1] {
+        Token::Id(ref n) => match input[1].token {
             Token::DoubleColon => {
                 if input.len() < 4 {
                     Err(Err::Incomplete(Needed::Size(4)))
-                } else { match input[2] {
-                    Token::Id(ref n2) => p_post_op(&input[3..], Box::new(ValExpr::QualId(get_package_ref(Some(n)), n2.clone()))),
-                    _ => Err(Err::Error(Context::Code(&input[2..], ErrorKind::Custom(100)))),
+                } else { match (&input[2].token, input.get(3).map(|t| &t.token), input.get(4).map(|t| &t.token)) {
+                    (Token::Id(ref e), Some(Token::DoubleColon), Some(Token::Id(ref v))) if is_package_ref(n) =>
+                        p_enum_variant(&input[5..], get_package_ref(Some(n)), e, v, input[0].span),
+                    (Token::Id(ref n2), _, _) if is_package_ref(n) => p_struct_or_post_op(&input[3..], get_package_ref(Some(n)), n2),
+                    (Token::Id(ref v), _, _) => {
+                        note_enum_ref(n, v, input[0].span);
+                        p_enum_variant(&input[3..], get_package_ref(None), n, v, input[0].span)
+                    },
+                    _ => expected(&input[2..], 100, "identifier"),
                 }}
             },
-            _ => p_post_op(&input[1..], Box::new(ValExpr::QualId(get_package_ref(None), n.clone()))),
+            _ => p_struct_or_post_op(&input[1..], get_package_ref(None), n),
         },
-        Token::Ref => match input[1] {
+        Token::Ref => match input[1].token {
             Token::Id(ref n) => p_post_op(&input[2..], Box::new(ValExpr::Ref(n.clone()))), // TODO: check size
-            _ => Err(Err::Error(Context::Code(&input[1..], ErrorKind::Custom(100)))),
+            _ => expected(&input[1..], 100, "identifier"),
         },
-        _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+        _ => expected(input, 100, "expression"),
     }}
 }
 
-fn p_unary<'a>(input: &'a [Token]) -> ExprRes<'a> {
+pub fn p_unary<'a>(input: &'a [SpannedToken]) -> ExprRes<'a> {
     if input.len() < 2 {
         Err(Err::Incomplete(Needed::Size(2)))
-    } else { match input[0] {
+    } else { match input[0].token {
         Token::Not => match p_atom(&input[1..]) {
-            Ok((ir,expr)) => Ok((ir, Box::new(NotExpr::new(expr)))),
+            Ok((ir,expr)) => Ok((ir, Box::new(NotExpr::new(expr, input[0].span)))),
             e => e,
         },
         Token::Minus => match p_atom(&input[1..]) {
-            Ok((ir,expr)) => Ok((ir, Box::new(NegExpr::new(expr)))),
+            Ok((ir,expr)) => Ok((ir, Box::new(NegExpr::new(expr, input[0].span)))),
             e => e,
         },
         _ => p_atom(&input),
     }}
 }
 
-fn p_mul_div<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 1 {
-        Err(Err::Incomplete(Needed::Size(1)))
-    } else { match p_unary(&input) {
-        Ok((ir,first)) => {
-            if ir.len() < 1 {
-                Err(Err::Incomplete(Needed::Size(1)))
-            } else { match ir[0] {
-                Token::Times => op_match!(ir, p_unary, first, MulExpr::new),
-                Token::Divide => op_match!(ir, p_unary, first, DivExpr::new),
-                Token::Modulo => op_match!(ir, p_unary, first, ModExpr::new),
-                _ => Ok((ir,first)),
-            }}
-        },
-        e => e,
-    }}
-}
 
-fn p_add_sub<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 1 {
-        Err(Err::Incomplete(Needed::Size(1)))
-    } else { match p_unary(&input) {
-        Ok((ir,first)) => {
-            if ir.len() < 1 {
-                Err(Err::Incomplete(Needed::Size(1)))
-            } else { match ir[0] {
-                Token::Times => op_match!(ir, p_unary, first, MulExpr::new),
-                Token::Divide => op_match!(ir, p_unary, first, DivExpr::new),
-                Token::Modulo => op_match!(ir, p_unary, first, ModExpr::new),
-                Token::Plus => op_match!(ir, p_mul_div, first, AddExpr::new),
-                Token::Minus => op_match!(ir, p_mul_div, first, SubExpr::new),
-                _ => Ok((ir,first)),
-            }}
-        },
-        e => e,
-    }}
-}
+#[cfg(test)]
+mod tests {
+    use ast::AstNode;
+    use expr_from_text;
 
-fn p_relational<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 1 {
-        Err(Err::Incomplete(Needed::Size(1)))
-    } else { match p_unary(&input) {
-        Ok((ir,first)) => {
-            if ir.len() < 1 {
-                Err(Err::Incomplete(Needed::Size(1)))
-            } else { match ir[0] {
-                Token::Times => op_match!(ir, p_unary, first, MulExpr::new),
-                Token::Divide => op_match!(ir, p_unary, first, DivExpr::new),
-                Token::Modulo => op_match!(ir, p_unary, first, ModExpr::new),
-                Token::Plus => op_match!(ir, p_mul_div, first, AddExpr::new),
-                Token::Minus => op_match!(ir, p_mul_div, first, SubExpr::new),
-                Token::GThan => op_match!(ir, p_add_sub, first, GThanExpr::new),
-                Token::GEq => op_match!(ir, p_add_sub, first, GEqExpr::new),
-                Token::LThan => op_match!(ir, p_add_sub, first, LThanExpr::new),
-                Token::LEq => op_match!(ir, p_add_sub, first, LEqExpr::new),
-                _ => Ok((ir,first)),
-            }}
-        },
-        e => e,
-    }}
-}
+    fn tree(input: &str) -> String {
+        expr_from_text(&[], input).ok().unwrap().print()
+    }
 
-fn p_equality<'a>(input: &'a [Token]) -> ExprRes<'a> {
-    if input.len() < 1 {
-        Err(Err::Incomplete(Needed::Size(1)))
-    } else { match p_unary(&input) {
-        Ok((ir,first)) => {
-            if ir.len() < 1 {
-                Err(Err::Incomplete(Needed::Size(1)))
-            } else { match ir[0] {
-                Token::Times => op_match!(ir, p_unary, first, MulExpr::new),
-                Token::Divide => op_match!(ir, p_unary, first, DivExpr::new),
-                Token::Modulo => op_match!(ir, p_unary, first, ModExpr::new),
-                Token::Plus => op_match!(ir, p_mul_div, first, AddExpr::new),
-                Token::Minus => op_match!(ir, p_mul_div, first, SubExpr::new),
-                Token::GThan => op_match!(ir, p_add_sub, first, GThanExpr::new),
-                Token::GEq => op_match!(ir, p_add_sub, first, GEqExpr::new),
-                Token::LThan => op_match!(ir, p_add_sub, first, LThanExpr::new),
-                Token::LEq => op_match!(ir, p_add_sub, first, LEqExpr::new),
-                Token::Equal => op_match!(input, p_relational, first, EqExpr::new),
-                Token::NEqual => op_match!(input, p_relational, first, NEqExpr::new),
-                Token::TrueEq => op_match!(input, p_relational, first, TrueEqExpr::new),
-                Token::TrueNEq => op_match!(input, p_relational, first, TrueNEqExpr::new),
-                _ => Ok((ir,first)),
-            }}
-        },
-        e => e,
-    }}
+    #[test]
+    fn precedence_arithmetic() {
+        assert_eq!(tree("a + b * c"), "(+ a (* b c))");
+        assert_eq!(tree("a * b + c"), "(+ (* a b) c)");
+        assert_eq!(tree("a % b - c / d"), "(- (% a b) (/ c d))");
+        assert_eq!(tree("(a + b) * c"), "(* (+ a b) c)");
+    }
+
+    #[test]
+    fn precedence_comparison() {
+        assert_eq!(tree("a + 1 < b * 2"), "(< (+ a 1) (* b 2))");
+        assert_eq!(tree("a < b == c >= d"), "(== (< a b) (>= c d))");
+        assert_eq!(tree("a == b & c != d"), "(& (== a b) (!= c d))");
+        assert_eq!(tree("a | b ^ c & d"), "(| a (^ b (& c d)))");
+        assert_eq!(tree("a === b | c !== d"), "(| (=== a b) (!== c d))");
+    }
+
+    #[test]
+    fn left_associativity() {
+        assert_eq!(tree("a - b - c"), "(- (- a b) c)");
+        assert_eq!(tree("a / b / c"), "(/ (/ a b) c)");
+        assert_eq!(tree("a - b + c"), "(+ (- a b) c)");
+        assert_eq!(tree("a == b == c"), "(== (== a b) c)");
+        assert_eq!(tree("a < b > c"), "(> (< a b) c)");
+        assert_eq!(tree("a & b & c"), "(& (& a b) c)");
+        assert_eq!(tree("a && b && c"), "(&& (&& a b) c)");
+        assert_eq!(tree("a || b || c"), "(|| (|| a b) c)");
+    }
+
+    #[test]
+    fn precedence_logical() {
+        assert_eq!(tree("a || b && c"), "(|| a (&& b c))");
+        assert_eq!(tree("a && b | c"), "(&& a (| b c))");
+        assert_eq!(tree("x != null && x.hp > 3"), "(&& (!= x null) (> (. x hp) 3))");
+    }
+
+    #[test]
+    fn unary_and_postfix() {
+        assert_eq!(tree("-a * b"), "(* (- a) b)");
+        assert_eq!(tree("!a & b"), "(& (! a) b)");
+        assert_eq!(tree("a - -b"), "(- a (- b))");
+        assert_eq!(tree("-a.b"), "(- (. a b))");
+        assert_eq!(tree("f(a + b, c)[0] * 2"), "(* (index (call f (+ a b) c) 0) 2)");
+        assert_eq!(tree("x->len() + 1"), "(+ (->len x) 1)");
+        assert_eq!(tree("[1, 2 + 3]"), "[1, (+ 2 3)]");
+        assert_eq!(tree("<a + 1, b * 2> == p"), "(== <(+ a 1), (* b 2)> p)");
+        assert_eq!(tree("<a, (b > c)>->first()"), "(->first <a, (> b c)>)");
+    }
+
+    #[test]
+    fn conditional() {
+        assert_eq!(tree("a ? b : c ? d : e"), "(? a b (? c d e))");
+        assert_eq!(tree("a || b ? c + 1 : d"), "(? (|| a b) (+ c 1) d)");
+        assert_eq!(tree("if a { b } elif c { d } else { e }"), "(? a b (? c d e))");
+        assert_eq!(tree("match a { 1: b, x: x, else: c }"), "(match a (1 b) (x x) (else c))");
+    }
 }
@@ -0,0 +1,81 @@
+use super::SpannedToken;
+use error::{Error, Type, CompileCode, UnexpectedToken};
+
+// used by compiler to report the furthest point parsing reached, and what was expected there
+pub struct Failure {
+    remaining: Option<usize>,
+    expected: Vec<String>,
+    function: Option<String>,
+}
+
+impl Failure {
+    pub fn new() -> Self {
+        Failure {
+            remaining: None,
+            expected: Vec::new(),
+            function: None,
+        }
+    }
+
+    // Positions are measured as the number of tokens left after them,
+    // so fewer remaining tokens means the parser got further.
+    pub fn record(&mut self, remaining: usize, expected: &str, function: Option<String>) {
+        match self.remaining {
+            Some(r) if r < remaining => {},
+            Some(r) if r == remaining => if !self.expected.iter().any(|e| e == expected) {
+                self.expected.push(expected.to_string());
+            },
+            _ => {
+                self.remaining = Some(remaining);
+                self.expected = vec![expected.to_string()];
+                self.function = function;
+            },
+        }
+    }
+
+    // Replaces everything expected at this position with a single description.
+    pub fn relabel(&mut self, remaining: usize, expected: &str) {
+        if self.remaining == Some(remaining) {
+            self.expected = vec![expected.to_string()];
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.remaining = None;
+        self.expected.clear();
+        self.function = None;
+    }
+
+    // Number of tokens left after the furthest failure, or after rest if nothing further was recorded.
+    pub fn furthest(&self, rest: usize) -> usize {
+        match self.remaining {
+            Some(r) if r < rest => r,
+            _ => rest,
+        }
+    }
+
+    // Builds the error for a failed parse of the input, at the given position.
+    pub fn to_error(&self, input: &[SpannedToken], remaining: usize) -> Error {
+        let (found, span) = match input.get(input.len() - remaining) {
+            Some(t) => (format!("`{}`", t.token), Some(t.span)),
+            None => ("end of input".to_string(), input.last().map(|t| t.span)),
+        };
+
+        let expected = if remaining == 0 || self.remaining != Some(remaining) {
+            Vec::new()
+        } else {
+            self.expected.clone()
+        };
+
+        let error = Error::new(Type::CompileTime(CompileCode::UnexpectedToken(Box::new(UnexpectedToken {
+            found,
+            expected,
+            function: self.function.clone(),
+        }))));
+
+        match span {
+            Some(s) => error.at(&s),
+            None => error,
+        }
+    }
+}
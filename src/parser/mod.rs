@@ -1,4 +1,5 @@
 mod resolver;
+mod failure;
 mod tokeniser;
 mod parser;
 mod expr;
@@ -6,6 +7,10 @@ mod expr;
 pub use self::parser::{parse_package, parse_snippet, parse_expr_snippet};
 pub use self::tokeniser::tokenise;
 
+use error::Span;
+
+use std::fmt;
+
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
@@ -14,6 +19,10 @@ pub enum Token {
     IntLit(i64),
     FloatLit(f64),
     StrLit(String),
+    // Literal parts of an interpolated string, around the tokens of each embedded expression
+    StrStart(String),
+    StrMid(String),
+    StrEnd(String),
     Null,
 
     // Keywords
@@ -34,6 +43,12 @@ pub enum Token {
     As,
     Ref,
     Match,
+    Try,
+    Catch,
+    Throw,
+    Struct,
+    Enum,
+    Yield,
 
     // Core functions?
 
@@ -55,6 +70,8 @@ pub enum Token {
     TrueEq,
     TrueNEq,
     Not,
+    LogicAnd,
+    LogicOr,
 
     // Assignment Ops
     AsnPlus,
@@ -76,9 +93,120 @@ pub enum Token {
     Colon,
     DoubleColon,
     SemiColon,
+    Question,
     Arrow,
     Dot,
     DoubleDot,
     LSq,
     RSq,
 }
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Token::*;
+        let text = match *self {
+            Id(ref n) => return write!(f, "{}", n),
+            IntLit(i) => return write!(f, "{}", i),
+            FloatLit(x) => return write!(f, "{:?}", x),
+            StrLit(ref s) => return write!(f, "\"{}\"", s),
+            StrStart(ref s) => return write!(f, "\"{}{{", s),
+            StrMid(ref s) => return write!(f, "}}{}{{", s),
+            StrEnd(ref s) => return write!(f, "}}{}\"", s),
+            Null => "null",
+
+            Var => "var",
+            Func => "func",
+            Return => "return",
+            If => "if",
+            Elif => "elif",
+            Else => "else",
+            While => "while",
+            True => "true",
+            False => "false",
+            For => "for",
+            In => "in",
+            Continue => "continue",
+            Break => "break",
+            Import => "import",
+            As => "as",
+            Ref => "ref",
+            Match => "match",
+            Try => "try",
+            Catch => "catch",
+            Throw => "throw",
+            Struct => "struct",
+            Enum => "enum",
+            Yield => "yield",
+
+            Plus => "+",
+            Minus => "-",
+            Times => "*",
+            Divide => "/",
+            Modulo => "%",
+            Or => "|",
+            Xor => "^",
+            And => "&",
+            GThan => ">",
+            GEq => ">=",
+            LThan => "<",
+            LEq => "<=",
+            Equal => "==",
+            NEqual => "!=",
+            TrueEq => "===",
+            TrueNEq => "!==",
+            Not => "!",
+            LogicAnd => "&&",
+            LogicOr => "||",
+
+            AsnPlus => "+=",
+            AsnMinus => "-=",
+            AsnTimes => "*=",
+            AsnDivide => "/=",
+            AsnModulo => "%=",
+            AsnOr => "|=",
+            AsnXor => "^=",
+            AsnAnd => "&=",
+            Assign => "=",
+
+            LBrac => "{",
+            RBrac => "}",
+            LPar => "(",
+            RPar => ")",
+            Comma => ",",
+            Colon => ":",
+            DoubleColon => "::",
+            SemiColon => ";",
+            Question => "?",
+            Arrow => "->",
+            Dot => ".",
+            DoubleDot => "..",
+            LSq => "[",
+            RSq => "]",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+
+// Token along with its location in the source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        SpannedToken {
+            token,
+            span,
+        }
+    }
+}
+
+impl PartialEq<Token> for SpannedToken {
+    fn eq(&self, other: &Token) -> bool {
+        self.token == *other
+    }
+}
@@ -1,11 +1,14 @@
-use super::Token;
+use super::{Token, SpannedToken};
 use super::resolver::Resolver;
+use super::failure::Failure;
+use super::expr::p_unary;
 
 use ast::*;
-use error::{Error, Type, CompileCode};
+use error::{Error, Span, Type, CompileCode, NonExhaustiveMatch};
+use runtime::TYPE_NAMES;
 
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeMap;
 
 use nom::{IResult, Needed, Err, ErrorKind, Context};
@@ -14,6 +17,26 @@ use nom::{IResult, Needed, Err, ErrorKind, Context};
 // For resolving context-specific package references
 thread_local!(static RESOLVER: RefCell<Resolver> = RefCell::new(Resolver::new()));
 
+// For reporting what the parser expected when it fails
+thread_local!(static FAILURE: RefCell<Failure> = RefCell::new(Failure::new()));
+
+// Struct literals can't be used directly in conditions, where `x {` starts the block
+thread_local!(static STRUCT_LITERALS: Cell<bool> = Cell::new(true));
+
+// For warning about matches that miss variants of an enum, once the enums are all known
+thread_local!(static ENUM_MATCHES: RefCell<Vec<EnumMatch>> = RefCell::new(Vec::new()));
+
+// Variants named as `Enum::Variant`, to check once the package's enums are all known
+thread_local!(static ENUM_REFS: RefCell<Vec<(String, String, Span)>> = RefCell::new(Vec::new()));
+
+// Match without an else on an enum from the package being parsed
+struct EnumMatch {
+    name: String,
+    covered: Vec<String>,
+    span: Span,
+    function: Option<String>,
+}
+
 pub fn get_package_ref(package_ref: Option<&str>) -> String {
     RESOLVER.with(|r| r.borrow()
                        .get_package_ref(package_ref)
@@ -21,6 +44,10 @@ pub fn get_package_ref(package_ref: Option<&str>) -> String {
                  )
 }
 
+pub fn is_package_ref(package_ref: &str) -> bool {
+    RESOLVER.with(|r| r.borrow().has_package_ref(package_ref))
+}
+
 pub fn add_package_ref(package_ref: &str, package_name: &str) {
     RESOLVER.with(|r| r.borrow_mut().add_package_ref(package_ref, package_name));
 }
@@ -33,73 +60,355 @@ pub fn clear_local_refs() {
     RESOLVER.with(|r| r.borrow_mut().clear_local_refs());
 }
 
+// Notes a use of `Enum::Variant` on an enum from the package being parsed.
+pub fn note_enum_ref(name: &str, variant: &str, span: Span) {
+    ENUM_REFS.with(|r| r.borrow_mut().push((name.to_string(), variant.to_string(), span)));
+}
 
-pub fn parse_package(input: &[Token], name: &str) -> Result<ScriptPackage, Error> {
-    RESOLVER.with(|r| r.borrow_mut().set_package(name));
+pub fn get_file() -> Option<String> {
+    RESOLVER.with(|r| r.borrow().get_file())
+}
 
-    let mut output = match p_func_list(input) {
-        Ok((_,o)) => o,
-        Err(_) => return Err(Error::new(Type::CompileTime(CompileCode::Error))),
+pub fn set_function(function_name: &str) {
+    RESOLVER.with(|r| r.borrow_mut().set_function(function_name));
+}
+
+pub fn clear_function() {
+    RESOLVER.with(|r| r.borrow_mut().clear_function());
+}
+
+pub fn struct_literals_allowed() -> bool {
+    STRUCT_LITERALS.with(|s| s.get())
+}
+
+fn with_struct_literals<T, F: FnOnce() -> T>(allow: bool, f: F) -> T {
+    let prev = STRUCT_LITERALS.with(|s| s.replace(allow));
+    let res = f();
+    STRUCT_LITERALS.with(|s| s.set(prev));
+    res
+}
+
+// Notes what the parser was looking for at the start of the input.
+pub fn expect(input: &[SpannedToken], what: &str) {
+    let function = RESOLVER.with(|r| r.borrow().get_function());
+    FAILURE.with(|f| f.borrow_mut().record(input.len(), what, function));
+}
+
+// Fails to parse, noting what was expected instead.
+pub fn expected<'a, O>(input: &'a [SpannedToken], code: u32, what: &str) -> IResult<&'a [SpannedToken], O> {
+    expect(input, what);
+    Err(Err::Error(Context::Code(input, ErrorKind::Custom(code))))
+}
+
+// Name shown in stack traces for a closure defined in the current function.
+fn closure_name() -> String {
+    match RESOLVER.with(|r| r.borrow().get_function()) {
+        Some(n) => format!("<closure in {}::{}>", get_package_ref(None), n),
+        None => "<closure>".to_string(),
+    }
+}
+
+// Converts a parse failure into a compile error located at the furthest token reached.
+// Also gives the number of tokens left from that point.
+fn compile_error(input: &[SpannedToken], e: Err<&[SpannedToken]>) -> (Error, usize) {
+    let remaining = match e {
+        Err::Error(Context::Code(i, _)) | Err::Failure(Context::Code(i, _)) => FAILURE.with(|f| f.borrow().furthest(i.len())),
+        Err::Incomplete(_) => 0,
     };
 
+    let error = FAILURE.with(|f| f.borrow().to_error(input, remaining));
+    FAILURE.with(|f| f.borrow_mut().reset());
+    (error, remaining)
+}
+
+// Replaces what was expected at the start of the input with a single description,
+// if parsing failed there.
+fn label<'a, O>(input: &'a [SpannedToken], res: IResult<&'a [SpannedToken], O>, what: &str) -> IResult<&'a [SpannedToken], O> {
+    if let Err(Err::Error(_)) = res {
+        FAILURE.with(|f| f.borrow_mut().relabel(input.len(), what));
+    }
+    res
+}
+
+
+pub fn parse_package(input: &[SpannedToken], name: &str) -> Result<ScriptPackage, Vec<Error>> {
+    RESOLVER.with(|r| r.borrow_mut().set_package(name));
+    RESOLVER.with(|r| r.borrow_mut().set_file(name));
+
     let mut package = BTreeMap::new();
+    let mut structs = BTreeMap::new();
+    let mut enums = BTreeMap::new();
+    let mut errors = Vec::new();
+
+    ENUM_MATCHES.with(|m| m.borrow_mut().clear());
+    ENUM_REFS.with(|r| r.borrow_mut().clear());
 
-    while let Some((n, f)) = output.pop() {
-        package.insert(n, f);
+    let mut rest = match p_import(input) {
+        Ok((r,_)) => r,
+        Err(_) => input,
+    };
+
+    while !rest.is_empty() {
+        FAILURE.with(|f| f.borrow_mut().reset());
+        clear_local_refs();
+
+        match p_item(rest) {
+            Ok((r,Item::Func(n,f))) => {
+                package.entry(n).or_insert(f);
+                rest = r;
+            },
+            Ok((r,Item::Struct(n,s))) => {
+                structs.entry(n).or_insert(s);
+                rest = r;
+            },
+            Ok((r,Item::Enum(n,e))) => {
+                enums.entry(n).or_insert(e);
+                rest = r;
+            },
+            Err(e) => {
+                let (error, remaining) = compile_error(input, e);
+                errors.push(error.in_file(name));
+                rest = recover(input, &input[(input.len() - remaining)..], &mut errors);
+            },
+        }
     }
 
     RESOLVER.with(|r| r.borrow_mut().reset_package_refs());
 
-    Ok(ScriptPackage::new(/*name, */package))
+    if errors.is_empty() {
+        errors = unknown_variants(&enums).into_iter().map(|e| e.in_file(name)).collect();
+    }
+
+    if errors.is_empty() {
+        let warnings = enum_match_warnings(&enums, name);
+        let mut package = ScriptPackage::new(/*name, */package, structs, enums);
+        package.warnings = warnings;
+        Ok(package)
+    } else {
+        Err(errors)
+    }
+}
+
+// Warns about each match on one of the package's enums that misses some of its variants.
+fn enum_match_warnings(enums: &BTreeMap<String, EnumRoot>, file: &str) -> Vec<Error> {
+    let matches = ENUM_MATCHES.with(|m| m.replace(Vec::new()));
+    let mut warnings = Vec::new();
+    let mut seen = Vec::new();
+
+    for m in matches {
+        // Matches in expressions can be parsed more than once.
+        if seen.contains(&(m.span.line, m.span.col)) {
+            continue;
+        }
+        seen.push((m.span.line, m.span.col));
+
+        let missing = match enums.get(&m.name) {
+            Some(e) => e.variant_names().into_iter().filter(|v| !m.covered.contains(v)).collect::<Vec<_>>(),
+            None => continue,
+        };
+
+        if !missing.is_empty() {
+            let warning = Type::Warning(CompileCode::NonExhaustiveMatch(Box::new(NonExhaustiveMatch {
+                name: m.name,
+                missing,
+                function: m.function,
+            })));
+            warnings.push(Error::new(warning).at(&m.span).in_file(file));
+        }
+    }
+
+    warnings
+}
+
+// Errors for each `Enum::Variant` that isn't a variant of one of the package's enums. Without
+// an enum of that name, it is most likely a misspelt package.
+fn unknown_variants(enums: &BTreeMap<String, EnumRoot>) -> Vec<Error> {
+    let refs = ENUM_REFS.with(|r| r.replace(Vec::new()));
+    let mut errors: Vec<Error> = Vec::new();
+
+    for (e, v, span) in refs {
+        let known = match enums.get(&e) {
+            Some(en) => en.variant_names().contains(&v),
+            None => false,
+        };
+
+        // Expressions can be parsed more than once.
+        if !known && !errors.iter().any(|x| x.span() == Some(span)) {
+            errors.push(Error::new(Type::CompileTime(CompileCode::UnknownVariant(format!("{}::{}", e, v)))).at(&span));
+        }
+    }
+
+    errors
+}
+
+// Notes the variants covered by a match on an enum of this package, if it has no else.
+// Cases with guards or payload tests don't count, as they may not match every instance.
+fn note_enum_match<T>(cases: &[(Pattern, T)], has_else: bool, span: Span) {
+    if has_else || cases.iter().any(|(p, _)| p.is_catch_all()) {
+        return;
+    }
+
+    let package = get_package_ref(None);
+    let mut name = None;
+    let mut covered = Vec::new();
+
+    for (p, _) in cases.iter() {
+        if let Pattern::Enum(ref pack, ref e, ref v, ref payload) = *p {
+            match name {
+                _ if *pack != package => continue,
+                Some(ref n) if n != e => continue,
+                _ => name = Some(e.clone()),
+            }
+
+            let whole = match *payload {
+                Some(ref items) => items.iter().all(|i| i.is_catch_all()),
+                None => true,
+            };
+            if whole {
+                covered.push(v.clone());
+            }
+        }
+    }
+
+    if let Some(name) = name {
+        let function = RESOLVER.with(|r| r.borrow().get_function());
+        ENUM_MATCHES.with(|m| m.borrow_mut().push(EnumMatch { name, covered, span, function }));
+    }
+}
+
+// Skips past a syntax error, then reads the rest of the function as a flat list of statements
+// to find any more errors. Returns the input from the start of the next function or struct.
+fn recover<'a>(input: &'a [SpannedToken], from: &'a [SpannedToken], errors: &mut Vec<Error>) -> &'a [SpannedToken] {
+    let file = get_file().unwrap_or_default();
+    let mut rest = skip_statement(from);
+
+    loop {
+        match rest.first().map(|t| &t.token) {
+            None => return rest,
+            Some(&Token::Func) | Some(&Token::Struct) | Some(&Token::Enum) if is_id(&rest[1..]).is_ok() => return rest,
+            // Left over from blocks and expressions the error interrupted.
+            Some(&Token::RBrac) | Some(&Token::SemiColon) | Some(&Token::RPar) | Some(&Token::RSq) |
+            Some(&Token::Comma) | Some(&Token::Else) | Some(&Token::Elif) => rest = &rest[1..],
+            Some(_) => {
+                FAILURE.with(|f| f.borrow_mut().reset());
+
+                match p_stat(rest) {
+                    Ok((r,_)) => rest = r,
+                    Err(e) => {
+                        let (error, remaining) = compile_error(input, e);
+                        errors.push(error.in_file(&file));
+
+                        // Always move forward, even if the statement failed on its first token.
+                        rest = if remaining < rest.len() {
+                            skip_statement(&input[(input.len() - remaining)..])
+                        } else {
+                            skip_statement(&rest[1..])
+                        };
+                    },
+                }
+            },
+        }
+    }
+}
+
+// Skips to just after the next semicolon, or to the next closing brace, function,
+// or keyword that starts a statement.
+fn skip_statement(input: &[SpannedToken]) -> &[SpannedToken] {
+    for (i, t) in input.iter().enumerate() {
+        match t.token {
+            Token::SemiColon => return &input[(i + 1)..],
+            Token::RBrac    |
+            Token::Func     |
+            Token::Struct   |
+            Token::Enum     |
+            Token::Var      |
+            Token::Return   |
+            Token::If       |
+            Token::Match    |
+            Token::While    |
+            Token::For      |
+            Token::Try      |
+            Token::Throw    |
+            Token::Yield    |
+            Token::Continue |
+            Token::Break    => return &input[i..],
+            _ => {},
+        }
+    }
+
+    &input[input.len()..]
 }
 
-pub fn parse_snippet(input: &[Token], packs: &[(String, String)]) -> Result<Script, Error> {
+pub fn parse_snippet(input: &[SpannedToken], packs: &[(String, String)]) -> Result<Script, Error> {
     RESOLVER.with(|r| r.borrow_mut().set_package("0"));
 
     for &(ref n, ref r) in packs.iter() {
         add_package_ref(n,r);
     }
 
+    FAILURE.with(|f| f.borrow_mut().reset());
+    ENUM_MATCHES.with(|m| m.borrow_mut().clear());
+    ENUM_REFS.with(|r| r.borrow_mut().clear());
+
     let output = match p_stat(input) {
         Ok((_,o)) => o,
-        Err(_) => return Err(Error::new(Type::CompileTime(CompileCode::Error))),
+        Err(e) => {
+            RESOLVER.with(|r| r.borrow_mut().reset_package_refs());
+            return Err(compile_error(input, e).0);
+        },
     };
 
     RESOLVER.with(|r| r.borrow_mut().reset_package_refs());
 
+    ENUM_MATCHES.with(|m| m.borrow_mut().clear());
+
+    // Snippets don't declare enums, so any `Enum::Variant` of their own is unknown.
+    if let Some(e) = unknown_variants(&BTreeMap::new()).into_iter().next() {
+        return Err(e);
+    }
+
+    // Only functions can be suspended by `yield`.
+    if output.can_yield() {
+        return Err(Error::new(Type::CompileTime(CompileCode::YieldOutsideFunction)));
+    }
+
     Ok(Script::new(output))
 }
 
-pub fn parse_expr_snippet(input: &[Token], packs: &[(String, String)]) -> Result<ScriptExpr, Error> {
+pub fn parse_expr_snippet(input: &[SpannedToken], packs: &[(String, String)]) -> Result<ScriptExpr, Error> {
     RESOLVER.with(|r| r.borrow_mut().set_package("0"));
 
     for &(ref n, ref r) in packs.iter() {
         add_package_ref(n,r);
     }
 
+    FAILURE.with(|f| f.borrow_mut().reset());
+    ENUM_MATCHES.with(|m| m.borrow_mut().clear());
+    ENUM_REFS.with(|r| r.borrow_mut().clear());
+
     let output = match p_expr_snippet(input) {
         Ok((_,o)) => o,
-        Err(_) => return Err(Error::new(Type::CompileTime(CompileCode::Error))),
+        Err(e) => {
+            RESOLVER.with(|r| r.borrow_mut().reset_package_refs());
+            return Err(compile_error(input, e).0);
+        },
     };
 
     RESOLVER.with(|r| r.borrow_mut().reset_package_refs());
 
+    ENUM_MATCHES.with(|m| m.borrow_mut().clear());
+
+    // Snippets don't declare enums, so any `Enum::Variant` of their own is unknown.
+    if let Some(e) = unknown_variants(&BTreeMap::new()).into_iter().next() {
+        return Err(e);
+    }
+
     Ok(ScriptExpr::new(Some(output)))
 }
 
 
-named!(p_func_list<&[Token], Vec<(String, FuncRoot)> >,
-    do_parse!(
-        p_import    >>
-        f: many1!(
-            complete!(p_func)
-        )           >>
-        (f)
-    )
-);
-
-named!(p_import<&[Token], Vec<()> >,
-    many0!(
+named!(p_import<&[SpannedToken], Vec<()> >,
+    many0!(complete!(
         do_parse!(
             apply!(compare, Token::Import)  >>
             alt!(
@@ -125,31 +434,131 @@ named!(p_import<&[Token], Vec<()> >,
             )                               >>
             (())
         )
+    ))
+);
+
+// Top level declarations in a package
+enum Item {
+    Func(String, FuncRoot),
+    Struct(String, StructRoot),
+    Enum(String, EnumRoot),
+}
+
+named!(p_item<&[SpannedToken], Item>,
+    alt!(
+        map!(p_func, |(n, f)| Item::Func(n, f))     |
+        map!(p_struct, |(n, s)| Item::Struct(n, s)) |
+        map!(p_enum, |(n, e)| Item::Enum(n, e))
+    )
+);
+
+named!(p_struct<&[SpannedToken], (String, StructRoot)>,
+    do_parse!(
+        apply!(compare, Token::Struct)      >>
+        n: is_id                            >>
+        apply!(compare, Token::LBrac)       >>
+        fields: separated_list_complete!(
+            apply!(compare, Token::Comma),
+            alt!(
+                map!(apply!(p_method, Some(&n)), |(id, m)| (id, Some(m)))  |
+                do_parse!(
+                    id: is_id                           >>
+                    default: opt!(do_parse!(
+                        apply!(compare, Token::Assign)  >>
+                        e: p_expr                       >>
+                        (e)
+                    ))                                  >>
+                    ((id, default))
+                )
+            )
+        )                                   >>
+        opt!(apply!(compare, Token::Comma)) >>
+        apply!(compare, Token::RBrac)       >>
+        (n.clone(), StructRoot::new(n, fields))
+    )
+);
+
+named!(p_enum<&[SpannedToken], (String, EnumRoot)>,
+    do_parse!(
+        apply!(compare, Token::Enum)        >>
+        n: is_id                            >>
+        apply!(compare, Token::LBrac)       >>
+        variants: separated_list_complete!(
+            apply!(compare, Token::Comma),
+            do_parse!(
+                id: is_id                           >>
+                payload: opt!(do_parse!(
+                    apply!(compare, Token::LPar)    >>
+                    a: p_id_list                    >>
+                    apply!(compare, Token::RPar)    >>
+                    (a)
+                ))                                  >>
+                ((id, payload.unwrap_or_default()))
+            )
+        )                                   >>
+        opt!(apply!(compare, Token::Comma)) >>
+        apply!(compare, Token::RBrac)       >>
+        (n.clone(), EnumRoot::new(get_package_ref(None), n, variants))
     )
 );
 
-named!(p_func<&[Token], (String, FuncRoot)>,
+// Method `func name(args) {...}`, declared in a struct or object literal as a field holding a closure.
+// Methods of a struct are named after it, and others are named like closures.
+pub fn p_method<'a>(input: &'a [SpannedToken], owner: Option<&str>) -> IResult<&'a [SpannedToken], (String, Box<dyn Expr>)> {
+    let outer = RESOLVER.with(|r| r.borrow().get_function());
+
+    let res = do_parse!(input,
+        apply!(compare, Token::Func)    >>
+        n: is_id                        >>
+        name: value!(match owner {
+            Some(o) => {
+                set_function(&format!("{}.{}", o, n));
+                format!("{}.{}", o, n)
+            },
+            None => closure_name(),
+        })                              >>
+        apply!(compare, Token::LPar)    >>
+        a: p_id_list                    >>
+        apply!(compare, Token::RPar)    >>
+        apply!(compare, Token::LBrac)   >>
+        c: p_func_body                  >>
+        apply!(compare, Token::RBrac)   >>
+        (n, Box::new(ValExpr::Closure( Rc::new(RefCell::new(FuncRoot::new(a,c,get_package_ref(None),name,get_file()))) )) as Box<dyn Expr>)
+    );
+
+    if owner.is_some() {
+        match outer {
+            Some(o) => set_function(&o),
+            None => clear_function(),
+        }
+    }
+    res
+}
+
+named!(p_func<&[SpannedToken], (String, FuncRoot)>,
     do_parse!(
         apply!(compare, Token::Func)    >>
         n: is_id                        >>
+        value!(set_function(&n))        >>
         apply!(compare, Token::LPar)    >>
         a: p_id_list                    >>
         apply!(compare, Token::RPar)    >>
         apply!(compare, Token::LBrac)   >>
         c: p_func_body                  >>
         apply!(compare, Token::RBrac)   >>
-        (n, FuncRoot::new(a,c))
+        value!(clear_function())        >>
+        (n.clone(), FuncRoot::new(a,c,get_package_ref(None),n,get_file()))
     )
 );
 
-named!(p_id_list<&[Token], Vec<String> >,
+named!(p_id_list<&[SpannedToken], Vec<String> >,
     separated_list_complete!(
         apply!(compare, Token::Comma),
         is_id
     )
 );
 
-named!(p_func_body<&[Token], Vec<Box<dyn Statement> > >,
+named!(p_func_body<&[SpannedToken], Vec<Box<dyn Statement> > >,
     do_parse!(
         p_func_imports  >>
         s: p_stat_list  >>
@@ -158,7 +567,7 @@ named!(p_func_body<&[Token], Vec<Box<dyn Statement> > >,
     )
 );
 
-named!(p_func_imports<&[Token], Vec<()> >,
+named!(p_func_imports<&[SpannedToken], Vec<()> >,
     many0!(
         do_parse!(
             apply!(compare, Token::Import)  >>
@@ -188,13 +597,17 @@ named!(p_func_imports<&[Token], Vec<()> >,
     )
 );
 
-named!(p_stat_list<&[Token], Vec<Box<dyn Statement> > >,
+named!(p_stat_list<&[SpannedToken], Vec<Box<dyn Statement> > >,
     many0!(
         p_stat
     )
 );
 
-named!(p_stat<&[Token], Box<dyn Statement> >,
+fn p_stat(input: &[SpannedToken]) -> IResult<&[SpannedToken], Box<dyn Statement>> {
+    label(input, p_any_stat(input), "statement")
+}
+
+named!(p_any_stat<&[SpannedToken], Box<dyn Statement> >,
     alt!(
         p_scope         |
         p_return_stat   |
@@ -202,15 +615,19 @@ named!(p_stat<&[Token], Box<dyn Statement> >,
         p_match_stat    |
         p_while_stat    |
         p_for_stat      |
+        p_try_stat      |
+        p_throw_stat    |
+        p_yield_stat    |
         p_continue_stat |
         p_break_stat    |
         p_decl_stat     |
+        p_destructure_stat  |
         p_assign_stat   |
         p_expr_stat
     )
 );
 
-named!(p_scope<&[Token], Box<dyn Statement> >,
+named!(p_scope<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
         apply!(compare, Token::LBrac)   >>
         stats: p_stat_list              >>
@@ -219,33 +636,36 @@ named!(p_scope<&[Token], Box<dyn Statement> >,
     )
 );
 
-named!(p_return_stat<&[Token], Box<dyn Statement> >,
+named!(p_return_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                          >>
         apply!(compare, Token::Return)      >>
         expr: opt!(p_expr)                  >>
         apply!(compare, Token::SemiColon)   >>
-        (Box::new(ReturnStat::new(expr)))
+        (Box::new(ReturnStat::new(expr, sp)))
     )
 );
 
-named!(p_if_stat<&[Token], Box<dyn Statement> >,
+named!(p_if_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                  >>
         apply!(compare, Token::If)  >>
-        cond: p_expr                >>
+        cond: p_cond                >>
         then: p_stat                >>
         elif: opt!(p_elif)          >>
-        (Box::new(IfStat::new(cond, then, elif)))
+        (Box::new(IfStat::new(cond, then, elif, sp)))
     )
 );
 
-named!(p_elif<&[Token], Box<dyn Statement> >,
+named!(p_elif<&[SpannedToken], Box<dyn Statement> >,
     alt!(
         do_parse!(
+            sp: p_span                      >>
             apply!(compare, Token::Elif)    >>
-            cond: p_expr                    >>
+            cond: p_cond                    >>
             then: p_stat                    >>
             elif: opt!(p_elif)              >>
-            (Box::new(IfStat::new(cond, then, elif)) as Box<dyn Statement>)
+            (Box::new(IfStat::new(cond, then, elif, sp)) as Box<dyn Statement>)
         )   |
         do_parse!(
             apply!(compare, Token::Else)    >>
@@ -255,10 +675,11 @@ named!(p_elif<&[Token], Box<dyn Statement> >,
     )
 );
 
-named!(p_match_stat<&[Token], Box<dyn Statement> >,
+named!(p_match_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                      >>
         apply!(compare, Token::Match)   >>
-        cond: p_expr                    >>
+        cond: p_cond                    >>
         apply!(compare, Token::LBrac)   >>
         cases: many1!(p_match_case)     >>
         otherwise: opt!(do_parse!(
@@ -268,53 +689,300 @@ named!(p_match_stat<&[Token], Box<dyn Statement> >,
             (stat)
         ))                              >>
         apply!(compare, Token::RBrac)   >>
-        (Box::new(MatchStat::new(cond, cases, otherwise)))
+        value!(note_enum_match(&cases, otherwise.is_some(), sp)) >>
+        (Box::new(MatchStat::new(cond, cases, otherwise, sp)))
     )
 );
 
-named!(p_match_case<&[Token], (CaseType, Box<dyn Statement>)>,
+named!(p_match_case<&[SpannedToken], (Pattern, Box<dyn Statement>)>,
     do_parse!(
-        t: alt!(
-            do_parse!(
-                id: is_id                       >>
-                apply!(compare, Token::Colon)   >>
-                (CaseType::Var(id))
-            ) |
-            do_parse!(
-                e: p_expr                       >>
-                apply!(compare, Token::Colon)   >>
-                (CaseType::Value(e))
-            )
-        )               >>
+        t: p_case       >>
         stat: p_stat    >>
         ((t, stat))
     )
 );
 
-named!(p_while_stat<&[Token], Box<dyn Statement> >,
+named!(p_case<&[SpannedToken], Pattern>,
+    do_parse!(
+        pattern: p_pattern                  >>
+        guard: opt!(do_parse!(
+            apply!(compare, Token::If)  >>
+            cond: p_expr                >>
+            (cond)
+        ))                                  >>
+        apply!(compare, Token::Colon)       >>
+        (match guard {
+            Some(g) => Pattern::Guard(Box::new(pattern), g),
+            None => pattern,
+        })
+    )
+);
+
+// Patterns directly in a case can compare against any expression. Nested patterns
+// only compare against unary expressions, so they can be followed by `>` or `..`.
+fn p_pattern(input: &[SpannedToken]) -> IResult<&[SpannedToken], Pattern> {
+    p_pattern_with(input, p_expr)
+}
+
+fn p_sub_pattern(input: &[SpannedToken]) -> IResult<&[SpannedToken], Pattern> {
+    p_pattern_with(input, p_unary)
+}
+
+type ExprParser<'a> = fn(&'a [SpannedToken]) -> IResult<&'a [SpannedToken], Box<dyn Expr>>;
+
+fn p_pattern_with<'a>(input: &'a [SpannedToken], value: ExprParser<'a>) -> IResult<&'a [SpannedToken], Pattern> {
+    alt!(input,
+        call!(p_list_pattern, p_sub_pattern)    |
+        call!(p_obj_pattern, p_sub_pattern)     |
+        call!(p_pair_pattern, p_sub_pattern)    |
+        p_type_pattern                          |
+        p_enum_pattern                          |
+        do_parse!(
+            id: is_id           >>
+            pattern_end         >>
+            (Pattern::Var(id))
+        )   |
+        do_parse!(
+            lo: call!(value)                    >>
+            apply!(compare, Token::DoubleDot)   >>
+            hi: call!(value)                    >>
+            (Pattern::Range(lo, hi))
+        )   |
+        map!(call!(value), Pattern::Value)
+    )
+}
+
+// Patterns in declarations and assignments can only bind names, not test values.
+fn p_bind_pattern(input: &[SpannedToken]) -> IResult<&[SpannedToken], Pattern> {
+    alt!(input,
+        call!(p_list_pattern, p_bind_pattern)   |
+        call!(p_obj_pattern, p_bind_pattern)    |
+        call!(p_pair_pattern, p_bind_pattern)   |
+        map!(is_id, Pattern::Var)
+    )
+}
+
+type PatternParser<'a> = fn(&'a [SpannedToken]) -> IResult<&'a [SpannedToken], Pattern>;
+
+fn p_list_pattern<'a>(input: &'a [SpannedToken], sub: PatternParser<'a>) -> IResult<&'a [SpannedToken], Pattern> {
+    do_parse!(input,
+        apply!(compare, Token::LSq)         >>
+        items: many0!(do_parse!(
+            p: call!(sub)       >>
+            alt!(apply!(compare, Token::Comma) | peek!(apply!(compare, Token::RSq))) >>
+            (p)
+        ))                                  >>
+        rest: opt!(do_parse!(
+            apply!(compare, Token::DoubleDot)   >>
+            id: opt!(is_id)                     >>
+            (Box::new(id.map_or(Pattern::Any, Pattern::Var)))
+        ))                                  >>
+        apply!(compare, Token::RSq)         >>
+        (Pattern::List(items, rest))
+    )
+}
+
+fn p_obj_pattern<'a>(input: &'a [SpannedToken], sub: PatternParser<'a>) -> IResult<&'a [SpannedToken], Pattern> {
+    do_parse!(input,
+        apply!(compare, Token::LBrac)       >>
+        fields: many0!(do_parse!(
+            id: is_id           >>
+            p: opt!(do_parse!(
+                apply!(compare, Token::Colon)   >>
+                p: call!(sub)                   >>
+                (p)
+            ))                  >>
+            alt!(apply!(compare, Token::Comma) | peek!(apply!(compare, Token::RBrac))) >>
+            (match p {
+                Some(p) => (id, p),
+                None => (id.clone(), Pattern::Var(id)),
+            })
+        ))                                  >>
+        apply!(compare, Token::RBrac)       >>
+        (Pattern::Obj(fields))
+    )
+}
+
+fn p_pair_pattern<'a>(input: &'a [SpannedToken], sub: PatternParser<'a>) -> IResult<&'a [SpannedToken], Pattern> {
+    do_parse!(input,
+        apply!(compare, Token::LThan)   >>
+        a: call!(sub)                   >>
+        apply!(compare, Token::Comma)   >>
+        b: call!(sub)                   >>
+        apply!(compare, Token::GThan)   >>
+        (Pattern::Pair(Box::new(a), Box::new(b)))
+    )
+}
+
+// Enum variants, as `Enum::Variant` or `pack::Enum::Variant`, with optional payload patterns.
+fn p_enum_pattern(input: &[SpannedToken]) -> IResult<&[SpannedToken], Pattern> {
+    let tokens = input.iter().take(5).map(|t| &t.token).collect::<Vec<_>>();
+    let (rest, package, name, variant) = match tokens[..] {
+        [Token::Id(ref p), Token::DoubleColon, Token::Id(ref e), Token::DoubleColon, Token::Id(ref v)] if is_package_ref(p) =>
+            (&input[5..], get_package_ref(Some(p)), e.clone(), v.clone()),
+        [Token::Id(ref e), Token::DoubleColon, Token::Id(ref v), ..] if !is_package_ref(e) => {
+            note_enum_ref(e, v, input[0].span);
+            (&input[3..], get_package_ref(None), e.clone(), v.clone())
+        },
+        _ => return Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+    };
+
+    do_parse!(rest,
+        payload: opt!(do_parse!(
+            apply!(compare, Token::LPar)    >>
+            items: separated_list_complete!(apply!(compare, Token::Comma), p_sub_pattern) >>
+            apply!(compare, Token::RPar)    >>
+            (items)
+        ))                                  >>
+        (Pattern::Enum(package, name, variant, payload))
+    )
+}
+
+// Type tests: `x is int`, or `is int` without binding the value.
+named!(p_type_pattern<&[SpannedToken], Pattern>,
+    alt!(
+        do_parse!(
+            id: is_id                                       >>
+            apply!(compare, Token::Id("is".to_string()))    >>
+            t: type_name                                    >>
+            (Pattern::Type(Some(id), t))
+        )   |
+        do_parse!(
+            apply!(compare, Token::Id("is".to_string()))    >>
+            t: type_name                                    >>
+            (Pattern::Type(None, t))
+        )
+    )
+);
+
+named!(pub p_if_expr<&[SpannedToken], Box<dyn Expr> >,
+    do_parse!(
+        sp: p_span                  >>
+        apply!(compare, Token::If)  >>
+        cond: p_cond                >>
+        then: p_block_expr          >>
+        other: p_else_expr          >>
+        (Box::new(CondExpr::new(cond, then, other, sp)) as Box<dyn Expr>)
+    )
+);
+
+// Unlike the statement, an if expression must always end with an else.
+named!(p_else_expr<&[SpannedToken], Box<dyn Expr> >,
+    alt!(
+        do_parse!(
+            sp: p_span                      >>
+            apply!(compare, Token::Elif)    >>
+            cond: p_cond                    >>
+            then: p_block_expr              >>
+            other: p_else_expr              >>
+            (Box::new(CondExpr::new(cond, then, other, sp)) as Box<dyn Expr>)
+        )   |
+        do_parse!(
+            apply!(compare, Token::Else)    >>
+            other: p_block_expr             >>
+            (other)
+        )
+    )
+);
+
+named!(p_block_expr<&[SpannedToken], Box<dyn Expr> >,
+    do_parse!(
+        apply!(compare, Token::LBrac)   >>
+        expr: p_expr                    >>
+        apply!(compare, Token::RBrac)   >>
+        (expr)
+    )
+);
+
+named!(pub p_match_expr<&[SpannedToken], Box<dyn Expr> >,
+    do_parse!(
+        sp: p_span                      >>
+        apply!(compare, Token::Match)   >>
+        cond: p_cond                    >>
+        apply!(compare, Token::LBrac)   >>
+        cases: many0!(do_parse!(
+            t: p_case                       >>
+            expr: p_expr                    >>
+            opt!(apply!(compare, Token::Comma)) >>
+            ((t, expr))
+        ))                              >>
+        otherwise: opt!(do_parse!(
+            apply!(compare, Token::Else)    >>
+            apply!(compare, Token::Colon)   >>
+            expr: p_expr                    >>
+            opt!(apply!(compare, Token::Comma)) >>
+            (expr)
+        ))                              >>
+        apply!(compare, Token::RBrac)   >>
+        value!(note_enum_match(&cases, otherwise.is_some(), sp)) >>
+        (Box::new(MatchExpr::new(cond, cases, otherwise, sp)) as Box<dyn Expr>)
+    )
+);
+
+named!(p_while_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                      >>
         apply!(compare, Token::While)   >>
-        cond: p_expr                    >>
+        cond: p_cond                    >>
         body: p_stat                    >>
-        (Box::new(WhileStat::new(cond, body)))
+        (Box::new(WhileStat::new(cond, body, sp)))
     )
 );
 
-named!(p_for_stat<&[Token], Box<dyn Statement> >,
+named!(p_for_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                      >>
         apply!(compare, Token::For)     >>
         f: do_parse!(
-            element: is_id              >>
+            first: is_id                >>
+            second: opt!(preceded!(
+                apply!(compare, Token::Comma),
+                is_id
+            ))                          >>
             apply!(compare, Token::In)  >>
-            list: p_expr                >>
+            list: p_cond                >>
             body: p_stat                >>
-            (Box::new(ForStat::new(element, list, body)))
+            (match second {
+                Some(e) => Box::new(ForStat::new(Some(first), e, list, body, sp)),
+                None => Box::new(ForStat::new(None, first, list, body, sp)),
+            })
         )                               >>
         (f)
     )
 );
 
-named!(p_continue_stat<&[Token], Box<dyn Statement> >,
+named!(p_try_stat<&[SpannedToken], Box<dyn Statement> >,
+    do_parse!(
+        apply!(compare, Token::Try)     >>
+        body: p_stat                    >>
+        apply!(compare, Token::Catch)   >>
+        e: is_id                        >>
+        catch: p_stat                   >>
+        (Box::new(TryStat::new(body, e, catch)))
+    )
+);
+
+named!(p_yield_stat<&[SpannedToken], Box<dyn Statement> >,
+    do_parse!(
+        sp: p_span                          >>
+        apply!(compare, Token::Yield)       >>
+        expr: opt!(p_expr)                  >>
+        apply!(compare, Token::SemiColon)   >>
+        (Box::new(YieldStat::new(expr, sp)))
+    )
+);
+
+named!(p_throw_stat<&[SpannedToken], Box<dyn Statement> >,
+    do_parse!(
+        sp: p_span                          >>
+        apply!(compare, Token::Throw)       >>
+        expr: p_expr                        >>
+        apply!(compare, Token::SemiColon)   >>
+        (Box::new(ThrowStat::new(expr, sp)))
+    )
+);
+
+named!(p_continue_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
         apply!(compare, Token::Continue)    >>
         apply!(compare, Token::SemiColon)   >>
@@ -322,7 +990,7 @@ named!(p_continue_stat<&[Token], Box<dyn Statement> >,
     )
 );
 
-named!(p_break_stat<&[Token], Box<dyn Statement> >,
+named!(p_break_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
         apply!(compare, Token::Break)       >>
         apply!(compare, Token::SemiColon)   >>
@@ -330,32 +998,60 @@ named!(p_break_stat<&[Token], Box<dyn Statement> >,
     )
 );
 
-named!(p_decl_stat<&[Token], Box<dyn Statement> >,
+named!(p_decl_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                          >>
         apply!(compare, Token::Var)         >>
-        id: is_id                           >>
-        a: opt!(do_parse!(
-            apply!(compare, Token::Assign)  >>
-            e: p_expr                       >>
-            (e)
-        ))                                  >>
+        decl: alt!(
+            do_parse!(
+                id: is_id                           >>
+                a: opt!(do_parse!(
+                    apply!(compare, Token::Assign)  >>
+                    e: p_expr                       >>
+                    (e)
+                ))                                  >>
+                ((Pattern::Var(id), a))
+            )   |
+            do_parse!(
+                p: p_bind_pattern               >>
+                apply!(compare, Token::Assign)  >>
+                e: p_expr                       >>
+                ((p, Some(e)))
+            )
+        )                                   >>
+        apply!(compare, Token::SemiColon)   >>
+        (Box::new(VarDecl::new(decl.0, decl.1, sp)))
+    )
+);
+
+named!(p_destructure_stat<&[SpannedToken], Box<dyn Statement> >,
+    do_parse!(
+        sp: p_span                          >>
+        p: alt!(
+            call!(p_list_pattern, p_bind_pattern)   |
+            call!(p_obj_pattern, p_bind_pattern)    |
+            call!(p_pair_pattern, p_bind_pattern)
+        )                                   >>
+        apply!(compare, Token::Assign)      >>
+        e: p_expr                           >>
         apply!(compare, Token::SemiColon)   >>
-        (Box::new(VarDecl::new(&id, a)))
+        (Box::new(DestructureStat::new(p, e, sp)))
     )
 );
 
-named!(p_assign_stat<&[Token], Box<dyn Statement> >,
+named!(p_assign_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                          >>
         e: complete!(p_expr_no_consume)     >>
         var: is_id                          >>
         child_op: opt!(p_assign_op_chain)   >>
         expr: apply!(assign_op, e)          >>
         apply!(compare, Token::SemiColon)   >>
-        (Box::new(AssignStat::new(&var, expr, child_op)))
+        (Box::new(AssignStat::new(&var, expr, child_op, sp)))
     )
 );
 
-named!(p_assign_op_chain<&[Token], Box<dyn Assign> >,
+named!(p_assign_op_chain<&[SpannedToken], Box<dyn Assign> >,
     alt!(
         do_parse!(
             apply!(compare, Token::LSq)         >>
@@ -373,15 +1069,16 @@ named!(p_assign_op_chain<&[Token], Box<dyn Assign> >,
     )
 );
 
-named!(p_expr_stat<&[Token], Box<dyn Statement> >,
+named!(p_expr_stat<&[SpannedToken], Box<dyn Statement> >,
     do_parse!(
+        sp: p_span                          >>
         expr: complete!(p_expr)             >>
         apply!(compare, Token::SemiColon)   >>
-        (Box::new(ExprStat::new(expr)))
+        (Box::new(ExprStat::new(expr, sp)))
     )
 );
 
-named!(pub p_expr_snippet<&[Token], Box<dyn Expr> >,
+named!(pub p_expr_snippet<&[SpannedToken], Box<dyn Expr> >,
     do_parse!(
         expr: complete!(p_expr)             >>
         apply!(compare, Token::SemiColon)   >>
@@ -389,9 +1086,20 @@ named!(pub p_expr_snippet<&[Token], Box<dyn Expr> >,
     )
 );
 
-named!(pub p_expr<&[Token], Box<dyn Expr> >,
+pub fn p_expr(input: &[SpannedToken]) -> IResult<&[SpannedToken], Box<dyn Expr>> {
+    let res = with_struct_literals(true, || p_any_expr(input));
+    label(input, res, "expression")
+}
+
+// Condition before a block. Struct literals are allowed again inside brackets.
+fn p_cond(input: &[SpannedToken]) -> IResult<&[SpannedToken], Box<dyn Expr>> {
+    let res = with_struct_literals(false, || p_any_expr(input));
+    label(input, res, "expression")
+}
+
+named!(p_any_expr<&[SpannedToken], Box<dyn Expr> >,
     alt!(
-        call!(super::expr::p_expr_lalr) |
+        call!(super::expr::p_expr_pratt) |
         do_parse!(
             apply!(compare, Token::Func)    >>
             apply!(compare, Token::LPar)    >>
@@ -400,12 +1108,12 @@ named!(pub p_expr<&[Token], Box<dyn Expr> >,
             apply!(compare, Token::LBrac)   >>
             c: p_func_body                  >>
             apply!(compare, Token::RBrac)   >>
-            (Box::new(ValExpr::Closure( Rc::new(RefCell::new(FuncRoot::new(a,c))) )) as Box<dyn Expr>)
+            (Box::new(ValExpr::Closure( Rc::new(RefCell::new(FuncRoot::new(a,c,get_package_ref(None),closure_name(),get_file()))) )) as Box<dyn Expr>)
         )
     )
 );
 
-fn p_expr_no_consume<'a>(input: &'a [Token]) -> IResult<&'a [Token], Box<dyn Expr>> {
+fn p_expr_no_consume<'a>(input: &'a [SpannedToken]) -> IResult<&'a [SpannedToken], Box<dyn Expr>> {
     match p_expr(&input[0..]) {
         Ok((_,res)) => Ok((&input[0..],res)),
         e => e,
@@ -415,16 +1123,16 @@ fn p_expr_no_consume<'a>(input: &'a [Token]) -> IResult<&'a [Token], Box<dyn Exp
 macro_rules! assign_expr {
     ($input:ident, $id:ident, $op_expr:path) => {
         match p_expr(&$input[1..]) {
-            Ok((ir,expr)) => Ok((ir, Box::new($op_expr($id, expr)))),
+            Ok((ir,expr)) => Ok((ir, Box::new($op_expr($id, expr, $input[0].span)))),
             e => e,
         }
     };
 }
 
-fn assign_op<'a>(input: &'a [Token], id: Box<dyn Expr>) -> IResult<&'a [Token], Box<dyn Expr>> {
+fn assign_op<'a>(input: &'a [SpannedToken], id: Box<dyn Expr>) -> IResult<&'a [SpannedToken], Box<dyn Expr>> {
     if input.len() < 2 {
         Err(Err::Incomplete(Needed::Size(2)))
-    } else { match input[0] {
+    } else { match input[0].token {
         Token::Assign => p_expr(&input[1..]),
         Token::AsnPlus => assign_expr!(input, id, AddExpr::new),
         Token::AsnMinus => assign_expr!(input, id, SubExpr::new),
@@ -434,54 +1142,85 @@ fn assign_op<'a>(input: &'a [Token], id: Box<dyn Expr>) -> IResult<&'a [Token],
         Token::AsnOr => assign_expr!(input, id, OrExpr::new),
         Token::AsnXor => assign_expr!(input, id, XorExpr::new),
         Token::AsnAnd => assign_expr!(input, id, AndExpr::new),
-        _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(102)))),
+        _ => expected(input, 102, "assignment"),
     }}
 }
 
-fn compare(input: &[Token], t: Token) -> IResult<&[Token], &[Token]> {
+fn p_span(input: &[SpannedToken]) -> IResult<&[SpannedToken], Span> {
+    match input.first() {
+        Some(t) => Ok((input, t.span)),
+        None => Err(Err::Incomplete(Needed::Size(1))),
+    }
+}
+
+fn compare(input: &[SpannedToken], t: Token) -> IResult<&[SpannedToken], &[SpannedToken]> {
     if input.len() == 0 {
         Err(Err::Incomplete(Needed::Size(1)))
     } else if input[0] == t {
         Ok((&input[1..], &input[..0]))
     } else {
-        Err(Err::Error(Context::Code(input, ErrorKind::Custom(100))))
+        expected(input, 100, &format!("`{}`", t))
     }
 }
 
-fn is_id(input: &[Token]) -> IResult<&[Token], String> {
+fn is_id(input: &[SpannedToken]) -> IResult<&[SpannedToken], String> {
     if input.len() == 0 {
         Err(Err::Incomplete(Needed::Size(1)))
     } else {
-        match input[0] {
+        match input[0].token {
             Token::Id(ref s) => Ok((&input[1..], s.clone())),
-            _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(105)))),
+            _ => expected(input, 105, "identifier"),
         }
     }
 }
 
-fn is_str_lit(input: &[Token]) -> IResult<&[Token], String> {
-    if input.len() == 0 {
+fn type_name(input: &[SpannedToken]) -> IResult<&[SpannedToken], String> {
+    if input.is_empty() {
         Err(Err::Incomplete(Needed::Size(1)))
     } else {
-        match input[0] {
-            Token::StrLit(ref s) => Ok((&input[1..], s.clone())),
-            _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(106)))),
+        match input[0].token {
+            Token::Id(ref s) if TYPE_NAMES.contains(&s.as_str()) => Ok((&input[1..], s.clone())),
+            _ => expected(input, 105, "type name"),
         }
     }
 }
 
-fn end_function(input: &[Token]) -> IResult<&[Token], ()> {
-    clear_local_refs();
-    Ok((input, ()))
+// Checks for a token that can follow a pattern, without consuming it.
+fn pattern_end(input: &[SpannedToken]) -> IResult<&[SpannedToken], ()> {
+    if input.is_empty() {
+        Err(Err::Incomplete(Needed::Size(1)))
+    } else {
+        match input[0].token {
+            Token::Colon | Token::Comma | Token::RSq | Token::RBrac | Token::RPar | Token::GThan | Token::If => Ok((input, ())),
+            _ => Err(Err::Error(Context::Code(input, ErrorKind::Custom(100)))),
+        }
+    }
 }
 
-
+fn is_str_lit(input: &[SpannedToken]) -> IResult<&[SpannedToken], String> {
+    if input.len() == 0 {
+        Err(Err::Incomplete(Needed::Size(1)))
+    } else {
+        match input[0].token {
+            Token::StrLit(ref s) => Ok((&input[1..], s.clone())),
+            _ => expected(input, 106, "string"),
+        }
+    }
+}
+
+fn end_function(input: &[SpannedToken]) -> IResult<&[SpannedToken], ()> {
+    clear_local_refs();
+    Ok((input, ()))
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use runtime::{Value, FuncMap};
+    use runtime::{Value, FuncMap, Signal, NativeIter, Package, ExprRes, get_field};
     use parser::tokeniser::tokenise;
     use VType::*;
+    use std::collections::HashMap;
 
     /*#[test]
     fn parse_function() {
@@ -598,6 +1337,1288 @@ mod tests {
         assert_eq!(out, Ok(Value::Val(I(4))));
     }*/
 
+    #[test]
+    fn parse_try_catch() {
+        let input = "{var x = 0; try {x = 1; throw 5; x = 2;} catch e {x = x + e;} return x;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(6))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_logic_short_circuit() {
+        let input = "{var x = null; var n = 0; if x != null && x.hp > 3 {n = 1;} if x == null || x.hp {n += 2;} return n;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(2))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_conditional_expr() {
+        let input = "{var a = 3; var s = a > 5 ? 1 : 2; var t = if a == 3 { 10 } else { 20 }; return s + t + match a { 1: 0, x: x * 100 };}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(312))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_match_patterns() {
+        let input = "{var n = 0; for m in [{kind: \"hit\", dmg: 5}, [1, 2, 3], 7, \"s\"] {
+            match m {
+                {kind: \"hit\", dmg} if dmg > 10: n += 1000;
+                {dmg}: n += dmg;
+                [a, ..rest]: n += a * 10 + rest->len();
+                1..5: n += 10000;
+                x is int: n += x * 100;
+                else: n += 1;
+            }
+        } return n;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(718))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_match_object_pattern_type() {
+        let input = "{var r = []; for m in [5, {}, \"s\"] {
+            match m {
+                {}: r->append(\"obj\");
+                else: r->append(\"other\");
+            }
+        } return r;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[\"other\", \"obj\", \"other\"]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_destructuring() {
+        let input = "{var [x, y] = [1, 2]; var {hp, name: n} = {hp: 10, name: 3}; var [a, ..rest] = [4, 5, 6]; [x, y] = [y, x]; return x * 1000 + hp + n + a * rest->len();}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(2021))),
+            _ => panic!("expected return"),
+        }
+
+        let input = "{var [x, y, z] = [1, 2];}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        match script.run(&fm) {
+            Signal::Error(e) => assert_eq!(e.message(), "Runtime error: MissingElement"),
+            _ => panic!("expected error"),
+        }
+    }
+
+    #[test]
+    fn parse_destructuring_pair() {
+        let input = "func f(p) {
+            var <a, b> = p;
+            var c = 0;
+            <b, c> = p;
+            return a * 100 + b * 10 + c;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let pair = Value::Pair(Rc::new(RefCell::new(Value::Val(I(1)))), Rc::new(RefCell::new(Value::Val(I(2)))));
+        assert_eq!(fm.call_fn("root", "f", &[pair]).unwrap(), Value::Val(I(112)));
+    }
+
+    #[test]
+    fn parse_destructuring_wrong_shape() {
+        let input = "{
+            var <a, b> = [1, 2];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Error(e) => assert_eq!(e.message(), "Runtime error: TypeError"),
+            _ => panic!("expected error"),
+        }
+    }
+
+    #[test]
+    fn parse_pair() {
+        let input = "{var p = <1, 2 + 3>; var m = {[]}; m->insert(<5, 1>, 7); return p->swap() == <5, 1> && p->first() == 1 ? m[p->swap()] : 0;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(7))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_catch_runtime_error() {
+        let input = "{var l = [1, 2]; try {l[5];} catch e {return e.kind;} return null;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(Value::Str(s)) => assert_eq!(*s.borrow(), "OutOfBounds"),
+            _ => panic!("expected error object"),
+        }
+    }
+
+    #[test]
+    fn parse_catch_package_error() {
+        use error::{mserr, CustomError, Type};
+
+        struct Refused;
+
+        impl CustomError for Refused {
+            fn to_string(&self) -> String {
+                "refused".to_string()
+            }
+        }
+
+        let input = "{
+            try {
+                rust::open();
+            } catch e {
+                return e.kind;
+            }
+            return null;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[("rust".to_string(), "rust".to_string())]).unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("rust", Box::new(|_: &str, _: &[Value], _: &FuncMap| {
+            mserr(Type::Package(Box::new(Refused)))
+        }));
+
+        match script.run(&fm) {
+            Signal::Return(Value::Str(s)) => assert_eq!(*s.borrow(), "Package"),
+            _ => panic!("expected error object"),
+        }
+    }
+
+    #[test]
+    fn parse_catch_package_error_payload() {
+        use error::{mserr, CustomError, Type};
+
+        struct Refused;
+
+        impl CustomError for Refused {
+            fn to_string(&self) -> String {
+                "refused".to_string()
+            }
+
+            fn to_value(&self) -> Option<Value> {
+                Some(Value::Val(I(42)))
+            }
+        }
+
+        let input = "{
+            try {
+                rust::open();
+            } catch e {
+                return e.payload;
+            }
+            return null;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[("rust".to_string(), "rust".to_string())]).unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("rust", Box::new(|_: &str, _: &[Value], _: &FuncMap| {
+            mserr(Type::Package(Box::new(Refused)))
+        }));
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v, Value::Val(I(42))),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let input = "{var x = 1;\nvar y = x / 0;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Error(e) => assert_eq!(e.span(), Some(Span::new(2, 11))),
+            _ => panic!("expected error"),
+        }
+    }
+
+    #[test]
+    fn parse_error_expected() {
+        use error::{Type, CompileCode};
+
+        let input = "{var x = [1, 2;}";
+        let parsed = tokenise(input).unwrap();
+        let e = parse_snippet(&parsed, &[]).err().unwrap();
+
+        assert_eq!(e.span(), Some(Span::new(1, 15)));
+        match *e.get_type() {
+            Type::CompileTime(CompileCode::UnexpectedToken(ref u)) => {
+                assert_eq!(u.found, "`;`");
+                assert_eq!(u.expected, vec!["`,`".to_string(), "`]`".to_string()]);
+            },
+            _ => panic!("expected syntax error"),
+        }
+
+        let input = "func f() {\nreturn 1;\n}\nfunc g(a) {\nreturn a\n}";
+        let parsed = tokenise(input).unwrap();
+        let e = parse_package(&parsed, "root").err().unwrap();
+
+        assert_eq!(e[0].to_string(), "root:6:1: Compile error: unexpected `}`, expected `;` in function `g`");
+    }
+
+    #[test]
+    fn parse_error_recovery() {
+        let input = "func f() {\nvar x = 1\nvar y = [1, 2;\nif x { y = ; }\nreturn x;\n}\nfunc g() {return 1;}\nfunc h( {\nreturn 2 * ;\n}";
+        let parsed = tokenise(input).unwrap();
+        let errors = parse_package(&parsed, "root").err().unwrap();
+        let spans = errors.iter().map(|e| e.span().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![Span::new(3, 1), Span::new(3, 14), Span::new(4, 12), Span::new(8, 9), Span::new(9, 12)]);
+    }
+
+    #[test]
+    fn parse_stack_trace() {
+        let input = "func f(l) {\nvar g = func() {return h(l);};\nreturn g();}\nfunc h(l) {return l[3];}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let list = Value::List(Rc::new(RefCell::new(vec![Value::Val(I(1))])));
+        let e = fm.call_fn("root", "f", &[list]).unwrap_err();
+        let trace = e.trace();
+
+        assert_eq!(e.span(), Some(Span::new(4, 20)));
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[0].function, "h");
+        assert_eq!(trace[0].span, Some(Span::new(2, 25)));
+        assert_eq!(trace[1].function, "<closure in root::f>");
+        assert_eq!(trace[1].span, Some(Span::new(3, 9)));
+        assert_eq!(trace[2].function, "f");
+        assert_eq!(trace[2].span, None);
+    }
+
+    #[test]
+    fn parse_struct() {
+        let input = "struct Unit {name, hp = 10}\nfunc f(n) {var u = Unit{name: n}; if u.hp > 5 {u.hp += 1;} return u.hp;}\nfunc g() {return Unit{name: 1, mp: 2};}\nfunc h() {return Unit{hp: 2};}\nfunc k() {return Unit({name: 1});}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[Value::Null]).unwrap(), Value::Val(I(11)));
+
+        let e = fm.call_fn("root", "g", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: UnknownField");
+        assert_eq!(e.span(), Some(Span::new(3, 22)));
+
+        let e = fm.call_fn("root", "h", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: MissingField");
+
+        let e = fm.call_fn("root", "k", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: FunctionNotFound");
+
+        let fields = Value::Obj(Rc::new(RefCell::new(HashMap::new())), Rc::new(RefCell::new(None)));
+        let e = fm.call_fn("root", "Unit{}", &[fields]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: FunctionNotFound");
+    }
+
+    #[test]
+    fn parse_methods() {
+        let input = "import rust;\nstruct Unit {hp = 10, func heal(n) {self.hp += n; return self.hp;}}\nfunc add(n) {self.count += n; return self.count;}\nfunc f() {var u = Unit{}; u.heal(5); var c = {count: 1, func inc() {self.count += 1;}, add: add}; c.inc(); var n = {count: 3, get: rust::get}; return [u.heal(1), c.add(10), n.get(), Unit{} == Unit{}];}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        // Native method that calls back into the script before using `self`.
+        struct Native;
+        impl Package for Native {
+            fn call(&self, _: &str, _: &[Value], _: &FuncMap) -> ExprRes {
+                Ok(Value::Null)
+            }
+
+            fn call_method(&self, _: &str, this: &Value, _: &[Value], f: &FuncMap) -> ExprRes {
+                f.call_fn("root", "add", &[Value::Val(I(1))]).unwrap_err();
+                Ok(get_field(this, "count").unwrap())
+            }
+        }
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+        fm.attach_package("rust", Box::new(Native));
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[16, 12, 3, true]");
+
+        let e = fm.call_fn("root", "add", &[Value::Val(I(1))]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: VariableNotDeclared");
+    }
+
+    #[test]
+    fn parse_prototype() {
+        let input = "{var base = {hp: 10, func get() {return self.hp;}}; var orc = {name: \"orc\"}; orc->set_proto(base); orc.hp += 5; var {hp} = base; return [orc.get(), hp, orc->is_field(\"get\"), orc->get_proto()->is_field(\"name\")];}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        let fm = FuncMap::new();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[15, 10, true, false]"),
+            _ => panic!("expected return"),
+        }
+
+        let input = "{var a = {}; var b = {}; a->set_proto(b); b->set_proto(a);}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Error(e) => assert_eq!(e.message(), "Runtime error: CyclicPrototype"),
+            _ => panic!("expected error"),
+        }
+
+        let input = "{var base = {inner: {x: 1}}; var d = {}; d->set_proto(base); d.inner.x = 5;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Error(e) => assert_eq!(e.message(), "Runtime error: FieldNotFound"),
+            _ => panic!("expected error"),
+        }
+
+        let input = "{var base = {inner: {x: 1}}; var d = {}; d->set_proto(base); d.inner = base.inner->clone(); d.inner.x = 5; return [d.inner.x, base.inner.x];}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[5, 1]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_operator_hooks() {
+        let input = "struct V {x, func __add(o) {return V{x: self.x + o.x};}, func __lt(o) {return self.x < o.x;}, func __index(i) {return self.x * i;}, func __str() {return \"v\" + self.x->to_string();}}\nfunc f() {var a = V{x: 1}; var b = a + V{x: 2}; return [b.x, a < b, a >= b, b[10], \"is \" + b, (V{x: 4})->to_string(), [a, <b, 2>]->to_string()];}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[3, true, false, 30, \"is v3\", \"v4\", \"[v1, <v3, 2>]\"]");
+    }
+
+    #[test]
+    fn parse_operator_hooks_left_only() {
+        let input = "struct M {
+            v,
+            func __lt(o) {return self.v < o;},
+            func __eq(o) {return self.v == o;}
+        }
+        func gt(x) {return M{v: 5} > x;}
+        func le(x) {return M{v: 5} <= x;}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "gt", &[Value::Val(I(3))]).unwrap(), Value::Val(B(true)));
+        assert_eq!(fm.call_fn("root", "gt", &[Value::Val(I(5))]).unwrap(), Value::Val(B(false)));
+        assert_eq!(fm.call_fn("root", "le", &[Value::Val(I(5))]).unwrap(), Value::Val(B(true)));
+        assert_eq!(fm.call_fn("root", "le", &[Value::Val(I(3))]).unwrap(), Value::Val(B(false)));
+    }
+
+    #[test]
+    fn parse_interpolation() {
+        let input = "func f() {
+            var xs = [1, 2];
+            return f\"{xs->len()} items: {xs}, then {xs[0] + 1}\";
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "\"2 items: [1, 2], then 2\"");
+    }
+
+    #[test]
+    fn parse_interpolation_str_hook() {
+        let input = "struct U {name, func __str() {return '<' + self.name + '>';}}
+        func f() {
+            var u = U{name: \"orc\"};
+            return f\"{u} is here\";
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "\"<orc> is here\"");
+    }
+
+    #[test]
+    fn parse_interpolation_escaped_braces() {
+        let input = "func f() {
+            return f\"{{x}}\";
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "\"{x}\"");
+    }
+
+    #[test]
+    fn parse_interpolation_nested() {
+        let input = "func f() {
+            var x = 2;
+            return f'{f\"a{x}\"}';
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "\"a2\"");
+    }
+
+    #[test]
+    fn parse_plain_string_braces() {
+        let input = "func f() {
+            var u = 1;
+            return \"{u} {{\";
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "\"{u} {{\"");
+    }
+
+    #[test]
+    fn parse_interpolation_error() {
+        let input = "func f(a) {
+return f\"x {a +} y\";
+}";
+        let e = parse_package(&tokenise(input).unwrap(), "root").err().unwrap();
+        assert_eq!(e[0].to_string(), "root:2:16: Compile error: unexpected `} y\"`, expected expression in function `f`");
+    }
+
+    #[test]
+    fn parse_interpolation_unterminated() {
+        let e = tokenise("f\"x {a\"").unwrap_err();
+        assert_eq!(e.message(), "Compile error: unterminated `{` in string");
+    }
+
+    #[test]
+    fn parse_enum() {
+        let input = "enum State {Idle, Moving(target), Dead}\nfunc f(s) {match s {State::Idle: return 0; State::Moving(<x, y>): return x + y; State::Dead: return -1;}}\nfunc g(s) {\nreturn match s {State::Moving(t): 1, State::Idle: 2};\n}\nfunc h() {return State::Moving;}";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        assert_eq!(package.warnings.len(), 2);
+        assert_eq!(package.warnings[0].to_string(), "root:2:12: Compile warning: match on `State` has no case for `State::Moving` in function `f`");
+        assert_eq!(package.warnings[1].to_string(), "root:4:8: Compile warning: match on `State` has no case for `State::Dead` in function `g`");
+
+        let other = parse_package(&tokenise("enum State {Idle, Dead}").unwrap(), "other").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+        fm.attach_package("other", other.call_ref());
+
+        let input = "{var s = root::State::Moving(<1, 2>); return [root::f(s), root::f(root::State::Dead), root::g(root::State::Idle), s == root::State::Moving(<1, 2>), root::State::Idle == other::State::Idle, root::f(other::State::Dead)];}";
+        let parsed = tokenise(input).unwrap();
+        let packs = [("root".to_string(), "root".to_string()), ("other".to_string(), "other".to_string())];
+        let script = parse_snippet(&parsed, &packs).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[3, -1, 2, true, false, null]"),
+            _ => panic!("expected return"),
+        }
+
+        let e = fm.call_fn("root", "h", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: WrongNumberOfArguments");
+
+        let e = fm.call_fn("root", "State::Dead", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: FunctionNotFound");
+
+        let e = parse_package(&tokenise("enum State {Idle}\nfunc f() {return rot::f();}\nfunc g(s) {match s {State::Gone: return 1;}}").unwrap(), "root").err().unwrap();
+        assert_eq!(e.len(), 2);
+        assert_eq!(e[0].to_string(), "root:2:18: Compile error: unknown package or enum variant `rot::f`");
+        assert_eq!(e[1].message(), "Compile error: unknown package or enum variant `State::Gone`");
+
+        let e = parse_snippet(&tokenise("{return State::Idle;}").unwrap(), &[]).err().unwrap();
+        assert_eq!(e.message(), "Compile error: unknown package or enum variant `State::Idle`");
+    }
+
+    #[test]
+    fn parse_for_list_index() {
+        let input = "{
+            var r = [];
+            var xs = [5, 6];
+            for i, x in xs {
+                r->append(i + x);
+                xs->append(0);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[5, 7]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_for_string() {
+        let input = "{
+            var r = [];
+            for c in \"hé\" {
+                r->append(c);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[\"h\", \"é\"]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_for_object() {
+        let input = "{
+            var r = [];
+            for k, v in {b: 2, a: 1} {
+                r->append(<k, v>);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[<\"a\", 1>, <\"b\", 2>]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_for_map() {
+        let input = "{
+            var r = [];
+            for k in {[<1, 2>]= 3} {
+                r->append(k->second());
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[2]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_for_range_descending() {
+        let input = "func f() {
+            var r = [];
+            for i in [4..0] {
+                r->append(i);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[4, 3, 2, 1]");
+    }
+
+    #[test]
+    fn parse_for_range_step() {
+        let input = "func f() {
+            var r = [];
+            for i, x in [0..-5..-12] {
+                r->append(<i, x>);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[<0, 0>, <1, -5>, <2, -10>]");
+    }
+
+    #[test]
+    fn parse_for_next_hook() {
+        let input = "struct Count {
+            n = 0,
+            func __next() {
+                if self.n == 3 {return null;}
+                self.n += 1;
+                return self.n;
+            }
+        }
+        func f() {
+            var r = [];
+            for x in (Count{}) {
+                r->append(x);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn parse_for_native_iter() {
+        let input = "import rust;
+        func f() {
+            var r = [];
+            for x in rust::letters() {
+                r->append(x);
+            }
+            return r;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+        fm.attach_package("rust", Box::new(|_: &str, _: &[Value], _: &FuncMap| {
+            let letters = "ab".chars().map(|c| Value::Str(Rc::new(RefCell::new(c.to_string()))));
+            Ok(Value::Iter(Rc::new(RefCell::new(NativeIter::new(letters)))))
+        }));
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[\"a\", \"b\"]");
+    }
+
+    #[test]
+    fn parse_range_core_functions() {
+        let input = "func f() {
+            return [[0..3..10]->len(), [0..3]->to_list()];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[4, [0, 1, 2]]");
+    }
+
+    #[test]
+    fn parse_range_zero_step() {
+        let input = "func f() {
+            return [0..0..1];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let e = fm.call_fn("root", "f", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: InvalidRange");
+    }
+
+    #[test]
+    fn parse_range_index() {
+        let input = "{
+            var r = [10..-3..0];
+            return [r[1], r[-1]];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[7, 1]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_range_index_out_of_bounds() {
+        let input = "{
+            return [0..3][3];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Error(e) => assert_eq!(e.message(), "Runtime error: OutOfBounds"),
+            _ => panic!("expected error"),
+        }
+    }
+
+    #[test]
+    fn parse_range_list_equality() {
+        let input = "{
+            return [[0..3] == [0, 1, 2], [0, 1] != [0..3]];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[true, true]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_range_add_list() {
+        let input = "{
+            return [0..2] + [5];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[0, 1, 5]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_range_destructuring() {
+        let input = "{
+            var [a, ..rest] = [10..-3..0];
+            return [a, rest, rest->len()];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[10, [7..-3..0], 3]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_range_match() {
+        let input = "{
+            return match [0..3] {
+                [x, y]: 2,
+                [x, ..more]: more[1] * 10 + x,
+                else: -1
+            };
+        }";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[]).ok().unwrap();
+
+        match script.run(&FuncMap::new()) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "20"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_generator() {
+        let input = "func f() {
+            for x in [1..3] {
+                if x == 1 {
+                    yield \"one\";
+                } else {
+                    yield x * 10;
+                }
+            }
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let input = "{var r = []; for x in root::f() {r->append(x);} return r;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[("root".to_string(), "root".to_string())]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[\"one\", 20]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_generator_try_catch() {
+        let input = "func f() {
+            try {
+                yield 3;
+                throw 4;
+            } catch e {
+                yield e;
+            }
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let input = "{var r = []; for x in root::f() {r->append(x);} return r;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[("root".to_string(), "root".to_string())]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[3, 4]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_generator_nested() {
+        let input = "func count(n) {
+            var i = 0;
+            while i < n {
+                yield i;
+                i += 1;
+            }
+        }
+        func f() {
+            for c in count(2) {
+                yield c * 10;
+            }
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let input = "{var r = []; for x in root::f() {r->append(x);} return r;}";
+        let parsed = tokenise(input).unwrap();
+        let script = parse_snippet(&parsed, &[("root".to_string(), "root".to_string())]).ok().unwrap();
+
+        match script.run(&fm) {
+            Signal::Return(v) => assert_eq!(v.to_string(), "[0, 10]"),
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn parse_generator_next() {
+        let input = "func count(n) {
+            var i = 0;
+            while i < n {
+                yield i;
+                i += 1;
+            }
+            return \"done\";
+        }
+        func f() {
+            var c = count(1);
+            return [c->next(), c->next(), c->next()];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[0, null, null]");
+    }
+
+    #[test]
+    fn parse_yield_outside_function() {
+        let e = parse_snippet(&tokenise("{yield 1;}").unwrap(), &[]).err().unwrap();
+        assert_eq!(e.message(), "Compile error: `yield` outside of a function");
+    }
+
+    // Function map with the script package as `root`, and a `game` package whose functions ask
+    // the script to yield their argument.
+    fn game_funcs(input: &str) -> FuncMap {
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+        fm.attach_package("game", Box::new(|_: &str, a: &[Value], f: &FuncMap| {
+            f.request_yield(a[0].clone());
+            Ok(Value::Null)
+        }));
+        fm
+    }
+
+    // Resumes a coroutine of the root function six times, describing each result.
+    fn run_coroutine(fm: &FuncMap, name: &str) -> String {
+        let func = Value::Func(Rc::new(RefCell::new("root".to_string())), Rc::new(RefCell::new(name.to_string())));
+        let mut co = Coroutine::new(func);
+
+        (0..6).map(|_| match co.resume(fm) {
+            Ok(Resumed::Yielded(v)) => v.to_string(),
+            Ok(Resumed::Finished(v)) => format!("finished {}", v),
+            Err(e) => e.message(),
+        }).collect::<Vec<_>>().join(", ")
+    }
+
+    #[test]
+    fn parse_coroutine() {
+        let fm = game_funcs("import game;
+        func walk_to(n) {
+            for i in [0..n] {
+                game::wait(i);
+            }
+            return n * 10;
+        }
+        func main() {
+            var x = walk_to(2);
+            game::wait(x);
+            try {
+                walk_to(1);
+            } catch e {}
+            return \"done\";
+        }");
+
+        assert_eq!(run_coroutine(&fm, "main"), "0, 1, 20, 0, finished \"done\", finished null");
+    }
+
+    #[test]
+    fn parse_coroutine_generator() {
+        let fm = game_funcs("func gen() {
+            yield 1;
+        }");
+
+        assert_eq!(run_coroutine(&fm, "gen"), "1, finished null, finished null, finished null, finished null, finished null");
+    }
+
+    #[test]
+    fn parse_coroutine_yield_in_expression() {
+        let fm = game_funcs("import game;
+        func walk_to(n) {
+            game::wait(n);
+            return n;
+        }
+        func bad() {
+            var y = 1 + walk_to(1);
+        }");
+
+        assert!(run_coroutine(&fm, "bad").starts_with("Runtime error: CannotYield"));
+    }
+
+    #[test]
+    fn parse_coroutine_yield_in_condition() {
+        let fm = game_funcs("import game;
+        func cond() {
+            if game::wait(7) {
+                var a = 1;
+            }
+        }");
+
+        assert!(run_coroutine(&fm, "cond").starts_with("Runtime error: CannotYield"));
+    }
+
+    #[test]
+    fn parse_coroutine_yield_in_return() {
+        let fm = game_funcs("import game;
+        func ret() {
+            return game::wait(5);
+        }");
+
+        assert_eq!(run_coroutine(&fm, "ret"), "5, finished null, finished null, finished null, finished null, finished null");
+    }
+
+    #[test]
+    fn parse_yield_request_outside_coroutine() {
+        let fm = game_funcs("import game;
+        func plain() {
+            if game::wait(9) {}
+            return 1;
+        }
+        func other() {
+            return 2;
+        }");
+
+        // A request that can't be honoured fails where it is made, and isn't left for the next call.
+        let e = fm.call_fn("root", "plain", &[]).unwrap_err();
+        assert_eq!(e.message(), "Runtime error: CannotYield");
+        assert_eq!(fm.call_fn("root", "other", &[]).unwrap().to_string(), "2");
+    }
+
+    #[test]
+    fn parse_coroutine_returned_generator() {
+        let fm = game_funcs("import game;
+        func count() {
+            yield 1;
+            yield 2;
+        }
+        func plain() {
+            return count();
+        }
+        func later() {
+            game::wait(0);
+            return count();
+        }");
+
+        let func = |n: &str| Value::Func(Rc::new(RefCell::new("root".to_string())), Rc::new(RefCell::new(n.to_string())));
+
+        let mut co = Coroutine::new(func("plain"));
+        assert!(matches!(co.resume(&fm), Ok(Resumed::Finished(Value::Gen(_)))));
+
+        let mut co = Coroutine::new(func("later"));
+        assert_eq!(co.resume(&fm).unwrap(), Resumed::Yielded(Value::Val(I(0))));
+        assert!(matches!(co.resume(&fm), Ok(Resumed::Finished(Value::Gen(_)))));
+
+        let mut co = Coroutine::new(func("count"));
+        assert_eq!(co.resume(&fm).unwrap(), Resumed::Yielded(Value::Val(I(1))));
+    }
+
+    #[test]
+    fn parse_map() {
+        let input = "func dbl(x) {
+            return x * 2;
+        }
+        func f() {
+            var xs = [3, 1, 2];
+            var k = 10;
+            return [xs->map(func(x) {return x + k;}), xs->map(dbl)];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[[13, 11, 12], [6, 2, 4]]");
+    }
+
+    #[test]
+    fn parse_filter() {
+        let input = "func f() {
+            return [3, 1, 2]->filter(func(x) {return x > 1;});
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[3, 2]");
+    }
+
+    #[test]
+    fn parse_reduce() {
+        let input = "func f() {
+            return [3, 1, 2]->reduce(0, func(a, x) {return a + x;});
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "6");
+    }
+
+    #[test]
+    fn parse_find() {
+        let input = "func f() {
+            var xs = [3, 1, 2];
+            return [xs->find(func(x) {return x > 5;}), xs->find(func(x) {return x < 3;})];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[null, 1]");
+    }
+
+    #[test]
+    fn parse_any_all() {
+        let input = "func f() {
+            var xs = [3, 1, 2];
+            return [xs->any(func(x) {return x == 2;}), xs->all(func(x) {return x > 1;})];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[true, false]");
+    }
+
+    #[test]
+    fn parse_sort_lt_hook() {
+        let input = "struct V {n, func __lt(o) {return self.n < o.n;}}
+        func f() {
+            var vs = [V{n: 2}, V{n: 1}];
+            vs->sort();
+            return vs->map(func(v) {return v.n;});
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[1, 2]");
+    }
+
+    #[test]
+    fn parse_sort_by_stable() {
+        let input = "func f() {
+            var ps = [<1, \"b\">, <0, \"a\">, <1, \"a\">];
+            ps->sort_by(func(a, b) {return a->first() < b->first();});
+            return ps;
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[<0, \"a\">, <1, \"b\">, <1, \"a\">]");
+    }
+
+    #[test]
+    fn parse_sort_by_non_bool() {
+        let input = "func f() {
+            [1, 2]->sort_by(func(a, b) {return null;});
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let e = fm.call_fn("root", "f", &[]).unwrap_err();
+        assert!(e.message().starts_with("Runtime error: TypeError"));
+    }
+
+    #[test]
+    fn parse_max_min_by() {
+        let input = "func f() {
+            var less = func(a, b) {return a < b;};
+            return [[3, 1, 2]->max_by(less), [3..0]->min_by(less)];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[3, 1]");
+    }
+
+    #[test]
+    fn parse_higher_order_iterators() {
+        let input = "func dbl(x) {
+            return x * 2;
+        }
+        func count(n) {
+            for i in [0..n] {
+                yield i;
+            }
+        }
+        func f() {
+            return [
+                [1..4]->map(dbl),
+                count(5)->filter(func(x) {return x % 2 == 0;}),
+                [0..100]->find(func(x) {return x * x > 50;}),
+                count(4)->reduce(0, func(a, x) {return a + x;})
+            ];
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        assert_eq!(fm.call_fn("root", "f", &[]).unwrap().to_string(), "[[2, 4, 6], [0, 2, 4], 8, 6]");
+    }
+
+    #[test]
+    fn parse_sort_range() {
+        let input = "func f() {
+            [2..0]->sort();
+        }";
+        let parsed = tokenise(input).unwrap();
+        let package = parse_package(&parsed, "root").ok().unwrap();
+
+        let mut fm = FuncMap::new();
+        fm.attach_package("root", package.call_ref());
+
+        let e = fm.call_fn("root", "f", &[]).unwrap_err();
+        assert!(e.message().starts_with("Runtime error: CoreBaseTypeError"));
+    }
+
     #[test]
     fn parse_list() {
         let input = "x, y, z ";
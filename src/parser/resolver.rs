@@ -4,6 +4,8 @@ use std::collections::HashMap;
 // used by compiler to resolve references & ids
 pub struct Resolver {
     current_package: Option<String>,
+    current_file: Option<String>,
+    current_function: Option<String>,
     package_refs: HashMap<String, String>,
     local_refs: HashMap<String, String>,
 }
@@ -12,6 +14,8 @@ impl Resolver {
     pub fn new() -> Self {
         Resolver {
             current_package: None,
+            current_file: None,
+            current_function: None,
             package_refs: HashMap::new(),
             local_refs: HashMap::new(),
         }
@@ -21,10 +25,34 @@ impl Resolver {
         self.current_package = Some(package_name.to_string());
     }
 
+    pub fn set_file(&mut self, file_name: &str) {
+        self.current_file = Some(file_name.to_string());
+    }
+
+    pub fn get_file(&self) -> Option<String> {
+        self.current_file.clone()
+    }
+
+    pub fn set_function(&mut self, function_name: &str) {
+        self.current_function = Some(function_name.to_string());
+    }
+
+    pub fn clear_function(&mut self) {
+        self.current_function = None;
+    }
+
+    pub fn get_function(&self) -> Option<String> {
+        self.current_function.clone()
+    }
+
     pub fn add_package_ref(&mut self, package_ref: &str, package_name: &str) {
         self.package_refs.insert(package_ref.to_string(), package_name.to_string());
     }
 
+    pub fn has_package_ref(&self, package_ref: &str) -> bool {
+        self.local_refs.contains_key(package_ref) || self.package_refs.contains_key(package_ref)
+    }
+
     pub fn get_package_ref(&self, package_ref: Option<&str>) -> Result<String,Error> {
         match package_ref {
             Some(s) => match self.local_refs.get(s) {
@@ -44,6 +72,8 @@ impl Resolver {
 
     pub fn reset_package_refs(&mut self) {
         self.current_package = None;
+        self.current_file = None;
+        self.current_function = None;
         self.package_refs.clear();
     }
 
@@ -1,13 +1,326 @@
-use super::Token;
-use error::{Error, Type, CompileCode};
+use super::{Token, SpannedToken};
+use error::{Error, Type, CompileCode, Span};
 
-use nom::{multispace, alphanumeric, alpha, digit};
+use nom::{multispace, alphanumeric, alpha};
+use nom::types::CompleteStr;
 
-pub fn tokenise(input: &str) -> Result<Vec<Token>, Error> {
-    match p_token_list(input) {
-        Ok((_,o)) => Ok(o),
-        //Err(e) => Err(format!("Error: {:?}", e)),
-        Err(_) => Err(Error::new(Type::CompileTime(CompileCode::Error))),
+pub fn tokenise(input: &str) -> Result<Vec<SpannedToken>, Error> {
+    let mut tokens = Vec::new();
+    tokenise_at(input, Span::new(1, 1), &mut tokens)?;
+    Ok(tokens)
+}
+
+// Reads the tokens of text that starts at the span.
+fn tokenise_at(input: &str, mut span: Span, tokens: &mut Vec<SpannedToken>) -> Result<(), Error> {
+    let mut rest = CompleteStr(input);
+
+    loop {
+        if let Ok((r,_)) = p_whitespace(rest) {
+            advance(&mut span, &rest[..(rest.len() - r.len())]);
+            rest = r;
+        }
+
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(lit) = p_str_lit(&rest) {
+            let lit = lit.map_err(|c| Error::new(Type::CompileTime(c)).at(&span))?;
+            if lit.raw {
+                tokens.push(SpannedToken::new(Token::StrLit(lit.body.to_string()), span));
+            } else {
+                let mut start = span;
+                advance(&mut start, &rest[..lit.start]);
+                split_string(lit.body, lit.interp, span, start, tokens)?;
+            }
+            advance(&mut span, &rest[..lit.len]);
+            rest = CompleteStr(&rest[lit.len..]);
+            continue;
+        }
+
+        if let Some(num) = p_num_lit(&rest) {
+            let (t, len) = num.map_err(|c| Error::new(Type::CompileTime(c)).at(&span))?;
+            tokens.push(SpannedToken::new(t, span));
+            advance(&mut span, &rest[..len]);
+            rest = CompleteStr(&rest[len..]);
+            continue;
+        }
+
+        match p_token(rest) {
+            // A comment that was never closed would otherwise read as a divide.
+            Ok((r,t)) if !rest.starts_with("/*") => {
+                tokens.push(SpannedToken::new(t, span));
+                advance(&mut span, &rest[..(rest.len() - r.len())]);
+                rest = r;
+            },
+            _ => return Err(Error::new(Type::CompileTime(invalid_token(&rest))).at(&span)),
+        }
+    }
+}
+
+// A string literal read from the source. The body starts `start` bytes into the literal,
+// which is `len` bytes long in total.
+struct StrLiteral<'a> {
+    body: &'a str,
+    start: usize,
+    len: usize,
+    raw: bool,
+    interp: bool,
+}
+
+// Reads the string literal at the start of the text, if there is one. Strings are quoted with
+// `"` or `'`, or with three of either to span several lines. A raw string starts with `r`
+// and any number of `#`, and ends with the same quotes followed by the same number of `#`.
+// A string starting with `f` can have expressions in it.
+fn p_str_lit(text: &str) -> Option<Result<StrLiteral<'_>, CompileCode>> {
+    let (raw, quoted) = match text.strip_prefix('r') {
+        Some(r) if r.starts_with(&['"', '\'', '#'][..]) => (true, r),
+        _ => (false, text),
+    };
+    let (interp, quoted) = match quoted.strip_prefix('f') {
+        Some(f) if !raw && f.starts_with(&['"', '\''][..]) => (true, f),
+        _ => (false, quoted),
+    };
+    let hashes = quoted.len() - quoted.trim_start_matches('#').len();
+    let quoted = &quoted[hashes..];
+
+    let quote = match quoted.chars().next() {
+        Some(q) if (q == '"') || (q == '\'') => q.to_string(),
+        _ => return None,
+    };
+    let open = if quoted.starts_with(&quote.repeat(3)) { quote.repeat(3) } else { quote };
+    let close = open.clone() + &"#".repeat(hashes);
+
+    let mut start = text.len() - quoted.len() + open.len();
+    let rest = &text[start..];
+    let end = if raw { rest.find(&close) } else { find_unescaped(rest, &close) };
+    let mut body = match end {
+        Some(e) => &rest[..e],
+        None => return Some(Err(CompileCode::UnterminatedString)),
+    };
+    let len = start + body.len() + close.len();
+
+    // A newline straight after the opening quotes of a multi-line string isn't part of it.
+    if open.len() == 3 {
+        if let Some(b) = body.strip_prefix('\n') {
+            body = b;
+            start += 1;
+        }
+    }
+
+    Some(Ok(StrLiteral {body, start, len, raw, interp}))
+}
+
+// Position of the first match of the pattern that isn't escaped with a backslash.
+fn find_unescaped(text: &str, pattern: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if text[i..].starts_with(pattern) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+// Reads the number literal at the start of the text, if there is one, giving the token and its length.
+// Integers can be written in hex (`0x`), binary (`0b`) or octal (`0o`), floats can have an exponent,
+// and `_` can separate the digits of either.
+fn p_num_lit(text: &str) -> Option<Result<(Token, usize), CompileCode>> {
+    let digit_at = |i: usize| text[i..].starts_with(|c: char| c.is_ascii_digit());
+    if !(digit_at(0) || (text.starts_with('.') && digit_at(1))) {
+        return None;
+    }
+
+    let radix = match text.get(..2) {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        Some("0o") => 8,
+        _ => 10,
+    };
+    let len = if radix == 10 { decimal_len(text) } else { 2 + word_len(&text[2..]) };
+
+    // Letters straight after a number would otherwise be read as a separate name.
+    let lit = &text[..(len + word_len(&text[len..]))];
+    if lit.len() > len {
+        return Some(Err(CompileCode::InvalidNumber(lit.to_string())));
+    }
+
+    let digits = lit.get(2..).filter(|_| radix != 10).unwrap_or(lit).replace('_', "");
+    let token = if (radix == 10) && lit.contains(&['.', 'e', 'E'][..]) {
+        match digits.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(Token::FloatLit(f)),
+            Ok(_) => Err(CompileCode::NumberTooLarge(lit.to_string())),
+            Err(_) => Err(CompileCode::InvalidNumber(lit.to_string())),
+        }
+    } else {
+        match i64::from_str_radix(&digits, radix) {
+            Ok(i) => Ok(Token::IntLit(i)),
+            // Only well formed digits can fail by being too large.
+            Err(_) if !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix)) => Err(CompileCode::NumberTooLarge(lit.to_string())),
+            Err(_) => Err(CompileCode::InvalidNumber(lit.to_string())),
+        }
+    };
+    Some(token.map(|t| (t, len)))
+}
+
+// Length of the decimal number at the start of the text: digits, then optionally a fraction and an exponent.
+// A `.` followed by another is a range rather than a fraction.
+fn decimal_len(text: &str) -> usize {
+    let digits = |from: usize| from + text[from..].find(|c: char| !(c.is_ascii_digit() || (c == '_'))).unwrap_or(text.len() - from);
+    let mut len = digits(0);
+
+    if text[len..].starts_with('.') && !text[len..].starts_with("..") {
+        len += 1;
+        if text[len..].starts_with(|c: char| c.is_ascii_digit()) {
+            len = digits(len);
+        }
+    }
+
+    if let Some(exp) = text[len..].strip_prefix(&['e', 'E'][..]) {
+        let sign = if exp.starts_with(&['+', '-'][..]) { 1 } else { 0 };
+        if exp[sign..].starts_with(|c: char| c.is_ascii_digit()) {
+            len = digits(len + 1 + sign);
+        }
+    }
+    len
+}
+
+// Length of the run of letters, digits and underscores at the start of the text.
+fn word_len(text: &str) -> usize {
+    text.find(|c: char| !(c.is_alphanumeric() || (c == '_'))).unwrap_or(text.len())
+}
+
+// Splits an interpolated string literal at each `{expr}` inside it. The literal parts become
+// StrStart, StrMid and StrEnd tokens around the tokens of each expression, or a single StrLit
+// if there are none. `{{` and `}}` are literal braces, and backslash escapes are replaced.
+// Other strings only have their escapes replaced.
+fn split_string(text: &str, interp: bool, span: Span, mut pos: Span, tokens: &mut Vec<SpannedToken>) -> Result<(), Error> {
+    let mut literal = String::new();
+    let mut literal_span = span;
+    let mut started = false;
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+
+        if interp && (rest.starts_with("{{") || rest.starts_with("}}")) {
+            literal.push_str(&rest[..1]);
+            advance(&mut pos, &rest[..2]);
+            i += 2;
+        } else if rest.starts_with('\\') {
+            let (c, len) = escape(rest).map_err(|c| Error::new(Type::CompileTime(c)).at(&pos))?;
+            literal.push(c);
+            advance(&mut pos, &rest[..len]);
+            i += len;
+        } else if let Some(inner) = rest.strip_prefix('{').filter(|_| interp) {
+            let end = match closing_brace(inner) {
+                Some(e) => e,
+                None => return Err(Error::new(Type::CompileTime(CompileCode::UnterminatedInterpolation)).at(&pos)),
+            };
+
+            let part = if started { Token::StrMid(literal) } else { Token::StrStart(literal) };
+            tokens.push(SpannedToken::new(part, literal_span));
+            literal = String::new();
+            started = true;
+
+            advance(&mut pos, "{");
+            tokenise_at(&inner[..end], pos, tokens)?;
+            advance(&mut pos, &inner[..end]);
+
+            literal_span = pos;
+            advance(&mut pos, "}");
+            i += end + 2;
+        } else {
+            let c = rest.chars().next().unwrap();
+            literal.push(c);
+            advance(&mut pos, &rest[..c.len_utf8()]);
+            i += c.len_utf8();
+        }
+    }
+
+    let part = if started { Token::StrEnd(literal) } else { Token::StrLit(literal) };
+    tokens.push(SpannedToken::new(part, literal_span));
+    Ok(())
+}
+
+// Reads the backslash escape at the start of the text, giving the character and the length of the escape.
+fn escape(text: &str) -> Result<(char, usize), CompileCode> {
+    let c = match text[1..].chars().next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some('u') => return unicode_escape(text),
+        Some(c) if "\\\"'{}".contains(c) => c,
+        Some(c) => return Err(CompileCode::InvalidEscape(format!("\\{}", c))),
+        None => return Err(CompileCode::InvalidEscape("\\".to_string())),
+    };
+    Ok((c, 2))
+}
+
+// Reads an escape of the form `\u{1F600}`, with between one and six hex digits.
+fn unicode_escape(text: &str) -> Result<(char, usize), CompileCode> {
+    let close = text.char_indices().take(10).find(|&(_, c)| c == '}');
+    let end = match (text[2..].starts_with('{'), close) {
+        (true, Some((e, _))) => e,
+        _ => return Err(CompileCode::InvalidEscape("\\u".to_string())),
+    };
+    let digits = &text[3..end];
+
+    // from_str_radix would also take a leading `+`.
+    let hex = (1..=6).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_hexdigit());
+
+    match u32::from_str_radix(digits, 16).ok().and_then(std::char::from_u32) {
+        Some(c) if hex => Ok((c, end + 1)),
+        _ => Err(CompileCode::InvalidEscape(text[..(end + 1)].to_string())),
+    }
+}
+
+// Position of the `}` closing an embedded expression, skipping over nested braces and strings.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(i),
+            (None, '}') => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+// Describes why no token could be read from the start of the text.
+fn invalid_token(text: &str) -> CompileCode {
+    match text.chars().next() {
+        Some('/') => CompileCode::UnterminatedComment,
+        Some(c) => CompileCode::InvalidCharacter(c),
+        None => CompileCode::Error,
+    }
+}
+
+// Moves the span past the text.
+fn advance(span: &mut Span, text: &str) {
+    for c in text.chars() {
+        if c == '\n' {
+            span.line += 1;
+            span.col = 1;
+        } else {
+            span.col += 1;
+        }
     }
 }
 
@@ -31,44 +344,38 @@ const IMPORT: &'static str = "import";
 const AS: &'static str = "as";
 const REF: &'static str = "ref";
 const MATCH: &'static str = "match";
+const TRY: &'static str = "try";
+const CATCH: &'static str = "catch";
+const THROW: &'static str = "throw";
+const STRUCT: &'static str = "struct";
+const ENUM: &'static str = "enum";
+const YIELD: &'static str = "yield";
 
 
-named!(p_token_list<&str, Vec<Token> >,
+named!(p_whitespace<CompleteStr, Vec<usize> >,
     many0!(
-        complete!(p_token)
+        alt!(
+            p_comment   |
+            value!(0, multispace)
+        )
     )
 );
 
-named!(p_token<&str, Token>,
-    //ws?
-    do_parse!(
-        many0!(
-            do_parse!(
-                alt_complete!(p_comment)        >>
-                opt!(alt_complete!(multispace)) >>
-                (0)
-            )
-        )                               >>
-        t: alt!(
-            p_punctuators   |
-            p_operators     |
-            p_float_lit     |
-            p_int_lit       |
-            p_str_lit       |
-            p_keywords      |
-            p_id
-        )                               >>
-        opt!(alt_complete!(multispace)) >>
-        (t)
+named!(p_token<CompleteStr, Token>,
+    alt!(
+        p_punctuators   |
+        p_operators     |
+        p_keywords      |
+        p_id
     )
 );
 
-named!(p_comment<&str, usize>,
+named!(p_comment<CompleteStr, usize>,
     alt!(
         do_parse!(
-            tag!("//")          >>
-            take_until!("\n")   >>
-            tag!("\n")          >>
+            tag!("//")                      >>
+            take_while!(|c| c != '\n')      >>
+            opt!(tag!("\n"))                >>
             (0)
         )   |
         do_parse!(
@@ -80,43 +387,7 @@ named!(p_comment<&str, usize>,
     )
 );
 
-named!(p_int_lit<&str, Token>,
-    do_parse!(
-        i: digit >>
-        (Token::IntLit(str_to_int(i).unwrap()))
-    )
-);
-
-named!(p_float_lit<&str, Token>,
-    do_parse!(
-        i: opt!(digit)  >>
-        tag!(".")       >>
-        peek!(
-            not!(tag!("."))
-        )               >>
-        f: opt!(digit)  >>
-        (make_float(i,f).unwrap())
-    )
-);
-
-named!(p_str_lit<&str, Token>,
-    alt!(
-        do_parse!(
-            tag!("\"")              >>
-            s: take_until!("\"")    >>
-            tag!("\"")              >>
-            (Token::StrLit(s.to_string()))
-        )   |
-        do_parse!(
-            tag!("\'")              >>
-            s: take_until!("\'")    >>
-            tag!("\'")              >>
-            (Token::StrLit(s.to_string()))
-        )
-    )
-);
-
-named!(p_punctuators<&str, Token>,
+named!(p_punctuators<CompleteStr, Token>,
     alt!(
         value!(Token::LBrac, tag!("{"))         |
         value!(Token::RBrac, tag!("}"))         |
@@ -128,12 +399,14 @@ named!(p_punctuators<&str, Token>,
         value!(Token::SemiColon, tag!(";"))     |
         value!(Token::DoubleColon, tag!("::"))  |
         value!(Token::Colon, tag!(":"))         |
+        value!(Token::Question, tag!("?"))      |
         value!(Token::Arrow, tag!("->"))        |
-        value!(Token::DoubleDot, tag!(".."))
+        value!(Token::DoubleDot, tag!(".."))    |
+        value!(Token::Dot, tag!("."))
     )
 );
 
-named!(p_operators<&str, Token>,
+named!(p_operators<CompleteStr, Token>,
     alt!(
         value!(Token::AsnPlus, tag!("+="))      |
         value!(Token::AsnMinus, tag!("-="))     |
@@ -143,6 +416,8 @@ named!(p_operators<&str, Token>,
         value!(Token::AsnOr, tag!("|="))        |
         value!(Token::AsnXor, tag!("^="))       |
         value!(Token::AsnAnd, tag!("&="))       |
+        value!(Token::LogicAnd, tag!("&&"))     |
+        value!(Token::LogicOr, tag!("||"))      |
         value!(Token::Plus, tag!("+"))          |
         value!(Token::Minus, tag!("-"))         |
         value!(Token::Times, tag!("*"))         |
@@ -164,7 +439,7 @@ named!(p_operators<&str, Token>,
     )
 );
 
-named!(p_keywords<&str, Token>,
+named!(p_keywords<CompleteStr, Token>,
     do_parse!(
         t: alt!(
             value!(Token::Var, tag!(VAR))           |
@@ -184,61 +459,34 @@ named!(p_keywords<&str, Token>,
             value!(Token::Import, tag!(IMPORT))     |
             value!(Token::As, tag!(AS))             |
             value!(Token::Ref, tag!(REF))           |
-            value!(Token::Match, tag!(MATCH))
+            value!(Token::Match, tag!(MATCH))       |
+            value!(Token::Try, tag!(TRY))           |
+            value!(Token::Catch, tag!(CATCH))       |
+            value!(Token::Throw, tag!(THROW))       |
+            value!(Token::Struct, tag!(STRUCT))     |
+            value!(Token::Enum, tag!(ENUM))         |
+            value!(Token::Yield, tag!(YIELD))
         )                           >>
-        peek!(not!(alphanumeric))   >>
+        peek!(not!(alt!(alphanumeric | tag!("_"))))  >>
         (t)
     )
 );
 
-named!(p_id<&str, Token>,
+named!(p_id<CompleteStr, Token>,
     do_parse!(
-        peek!(alpha)    >>
+        peek!(alt!(alpha | tag!("_")))  >>
         id: take_while!(
             |c: char| c.is_alphanumeric() || (c == '_')
         )               >>
-        (Token::Id(id.to_string()))
+        (Token::Id(id.0.to_string()))
     )
 );
 
-fn str_to_int(s: &str) -> Result<i64, String> {
-    match s.parse::<i64>() {
-        Ok(i) => Ok(i),
-        Err(_) => Err(format!("Not an integer: {}", s)),
-    }
-}
-
-fn make_float(i: Option<&str>, f: Option<&str>) -> Result<Token, String> {
-    //use std::f64;
-    match (i,f) {
-        (Some(i), Some(f)) => {
-            let ten: f64 = 10.0;
-            let div = ten.powi(f.len() as i32);
-            match (str_to_int(i), str_to_int(f)) {
-                (Ok(i), Ok(f)) => Ok(Token::FloatLit((i as f64) + (f as f64) / div)),
-                (_,_) => Err(format!("Not a float: {}.{}", i, f)),
-            }
-        },
-        (Some(i), None) => match str_to_int(i) {
-            Ok(i) => Ok(Token::FloatLit(i as f64)),
-            _ => Err(format!("Not a float: {}.", i)),
-        },
-        (None, Some(f)) => {
-            let ten: f64 = 10.0;
-            let div = ten.powi(f.len() as i32);
-            match str_to_int(f) {
-                Ok(f) => Ok(Token::FloatLit((f as f64) / div)),
-                _ => Err(format!("Not a float: .{}", f)),
-            }
-        },
-        (None, None) => Ok(Token::Dot),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use parser::Token;
+    use error::Span;
 
     #[test]
     fn tokenise_numbers() {
@@ -252,6 +500,106 @@ mod tests {
         assert_eq!(tokenise(input).unwrap(), expect);
     }
 
+    #[test]
+    fn tokenise_escapes() {
+        let input = r#""a\"b\n\u{48}\{""#;
+        let expect = vec![Token::StrLit("a\"b\nH{".to_string())];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_raw_strings() {
+        let input = r###"r"\n{x}" r##""q""##"###;
+        let expect = vec![Token::StrLit("\\n{x}".to_string()), Token::StrLit("\"q\"".to_string())];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_multiline_strings() {
+        let input = "'''
+x
+y'''";
+        let expect = vec![Token::StrLit("x\ny".to_string())];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_string_braces() {
+        let input = r#"f"{{}}" "{x}" '{'"#;
+        let expect = vec![Token::StrLit("{}".to_string()), Token::StrLit("{x}".to_string()),
+                          Token::StrLit("{".to_string())];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_invalid_escapes() {
+        let e = tokenise("\"ab\n\\q\"").unwrap_err();
+        assert_eq!(e.to_string(), "2:1: Compile error: invalid escape `\\q` in string");
+
+        let e = tokenise("x = \"\\u{d800}\";").unwrap_err();
+        assert_eq!(e.message(), "Compile error: invalid escape `\\u{d800}` in string");
+
+        let e = tokenise("\"\\u{+41}\"").unwrap_err();
+        assert_eq!(e.message(), "Compile error: invalid escape `\\u{+41}` in string");
+    }
+
+    #[test]
+    fn tokenise_unterminated_raw_string() {
+        let e = tokenise("r#\"abc\"").unwrap_err();
+        assert_eq!(e.message(), "Compile error: unterminated string");
+    }
+
+    #[test]
+    fn tokenise_number_bases() {
+        let input = "0xff 0b1010 0o17";
+        let expect = vec![Token::IntLit(255), Token::IntLit(10), Token::IntLit(15)];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_number_separators() {
+        let input = "1_000";
+        let expect = vec![Token::IntLit(1000)];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_number_exponents() {
+        let input = "1e3 2.5E-1 .5e1";
+        let expect = vec![Token::FloatLit(1000.0), Token::FloatLit(0.25), Token::FloatLit(5.0)];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_number_range() {
+        let input = "[1..2]";
+        let expect = vec![Token::LSq, Token::IntLit(1), Token::DoubleDot, Token::IntLit(2), Token::RSq];
+
+        assert_eq!(tokenise(input).unwrap(), expect);
+    }
+
+    #[test]
+    fn tokenise_number_too_large() {
+        let e = tokenise("x = 9223372036854775808;").unwrap_err();
+        assert_eq!(e.to_string(), "1:5: Compile error: number `9223372036854775808` is too large");
+    }
+
+    #[test]
+    fn tokenise_invalid_numbers() {
+        let e = tokenise("0b102").unwrap_err();
+        assert_eq!(e.message(), "Compile error: invalid number `0b102`");
+
+        let e = tokenise("1e").unwrap_err();
+        assert_eq!(e.message(), "Compile error: invalid number `1e`");
+    }
+
     #[test]
     fn tokenise_function() {
         let input = "func f(x) {return x*2;}";
@@ -278,4 +626,23 @@ mod tests {
 
         assert_eq!(tokenise(input).unwrap(), expect);
     }
+
+    #[test]
+    fn tokenise_spans() {
+        let input = "var x = 1;\n  // comment\n  x += 22;";
+        let spans = tokenise(input).unwrap().iter().map(|t| t.span).collect::<Vec<_>>();
+        let expect = vec![Span::new(1,1), Span::new(1,5), Span::new(1,7), Span::new(1,9), Span::new(1,10),
+                          Span::new(3,3), Span::new(3,5), Span::new(3,8), Span::new(3,10)];
+
+        assert_eq!(spans, expect);
+    }
+
+    #[test]
+    fn tokenise_errors() {
+        let invalid = |input| tokenise(input).unwrap_err().to_string();
+
+        assert_eq!(invalid("var x = 3 @ 4;"), "1:11: Compile error: invalid character `@`");
+        assert_eq!(invalid("var x = \"abc;"), "1:9: Compile error: unterminated string");
+        assert_eq!(invalid("x;\n/* comment"), "2:1: Compile error: unterminated comment");
+    }
 }
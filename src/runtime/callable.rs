@@ -19,7 +19,7 @@ impl Callable {
     pub fn call(&self, f: &FuncMap, args: &[Value]) -> ExprRes {
         match self.base {
             Value::Func(ref package, ref name) => f.call_fn(&package.borrow(), &name.borrow(), args),
-            Value::Closure(ref func, _) => func.borrow().call(args, f, None),
+            Value::Closure(ref func, _) => func.borrow().call(args, f, None, None),
             Value::Null => Ok(Value::Null),
             _ => mserr(Type::RunTime(RunCode::InvalidCall)),
         }
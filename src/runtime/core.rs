@@ -1,19 +1,24 @@
 // Core type functions
-use super::{Value, VType, ExprRes, hash_value};
-use error::{mserr, Type, RunCode};
+use super::{Value, VType, ExprRes, FuncMap, hash_value, get_field, truthy, range_iter, range_len};
+use ast::{Generator, Resumed, ForIter, call_value, call_hook, less_than};
+use error::{mserr, Error, Type, RunCode};
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::slice;
+use std::iter;
 
-pub fn core_func_call(func: &str, base_type: Value, args: &[Value]) -> ExprRes {
+pub fn core_func_call(func: &str, base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
     match func {
-        "to_string" =>  to_string(base_type, args),
+        "to_string" =>  to_string(base_type, args, f),
         "to_float"  =>  to_float(base_type, args),
         "abs"       =>  abs(base_type, args),
         "floor"     =>  floor(base_type, args),
         "ceil"      =>  ceil(base_type, args),
         "round"     =>  round(base_type, args),
         "len"       =>  len(base_type, args),
-        // left, right OR first, second OR front, back
+        "first"     =>  first(base_type, args),
+        "second"    =>  second(base_type, args),
+        "swap"      =>  swap(base_type, args),
         "clone"     =>  clone(base_type, args),
         "concat"    =>  concat(base_type, args),
         "parse_num" =>  parse_num(base_type, args),
@@ -25,6 +30,21 @@ pub fn core_func_call(func: &str, base_type: Value, args: &[Value]) -> ExprRes {
         "is_field"  =>  is_field(base_type, args),
         "same"      =>  same(base_type, args),
         "insert"    =>  insert(base_type, args),
+        "set_proto" =>  set_proto(base_type, args),
+        "get_proto" =>  get_proto(base_type, args),
+        "to_list"   =>  to_list(base_type, args, f),
+        "next"      =>  next(base_type, args, f),
+        "map"       =>  map(base_type, args, f),
+        "filter"    =>  filter(base_type, args, f),
+        "reduce"    =>  reduce(base_type, args, f),
+        "find"      =>  find(base_type, args, f),
+        "any"       =>  any(base_type, args, f),
+        "all"       =>  all(base_type, args, f),
+        "for_each"  =>  for_each(base_type, args, f),
+        "sort"      =>  sort(base_type, args, f),
+        "sort_by"   =>  sort_by(base_type, args, f),
+        "min_by"    =>  min_by(base_type, args, f),
+        "max_by"    =>  max_by(base_type, args, f),
         /*"is_key"    =>  is_key(base_type, args),
         "is_value"  =>  is_value(base_type, args),
         "keys"      =>  keys(base_type, args),
@@ -33,7 +53,7 @@ pub fn core_func_call(func: &str, base_type: Value, args: &[Value]) -> ExprRes {
     }
 }
 
-fn to_string(base_type: Value, args: &[Value]) -> ExprRes {
+fn to_string(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
     use Value::*;
     use self::VType::*;
 
@@ -52,7 +72,57 @@ fn to_string(base_type: Value, args: &[Value]) -> ExprRes {
             B(true)         => Ok(Str(Rc::new(RefCell::new("true".to_string())))),
             B(false)        => Ok(Str(Rc::new(RefCell::new("false".to_string())))),
         },
-        _               => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+        Str(ref s)      => Ok(Str(Rc::new(RefCell::new(s.borrow().clone())))),
+        ref v           => Ok(Str(Rc::new(RefCell::new(show(v, f)?)))),
+    }
+}
+
+// Like Display, but calls the `__str` hook of objects and structs, including ones held in other values.
+fn show(val: &Value, f: &FuncMap) -> Result<String, Error> {
+    use Value::*;
+
+    if let Some(res) = call_hook(val, "__str", &[], f) {
+        return match res? {
+            Str(s) => Ok(s.borrow().clone()),
+            _ => Err(Error::new(Type::RunTime(RunCode::TypeError))),
+        };
+    }
+
+    let show_all = |vals: &[Value]| -> Result<String, Error> {
+        let vals = vals.iter().map(|v| show(v, f)).collect::<Result<Vec<_>, _>>()?;
+        Ok(vals.join(", "))
+    };
+
+    match *val {
+        Pair(ref a, ref b) => {
+            let pair = [a.borrow().clone(), b.borrow().clone()];
+            Ok(format!("<{}>", show_all(&pair)?))
+        },
+        List(ref l) => {
+            let l = l.borrow().clone();
+            Ok(format!("[{}]", show_all(&l)?))
+        },
+        Obj(ref o, _) | Struct(_, ref o) => {
+            let fields = o.borrow().iter().map(|(k,v)| (k.clone(), v.clone())).collect::<Vec<_>>();
+            let fields = fields.iter().map(|(k,v)| Ok(format!("{}: {}", k, show(v, f)?))).collect::<Result<Vec<_>, Error>>()?;
+            match *val {
+                Struct(ref n, _) => Ok(format!("{}{{{}}}", n, fields.join(", "))),
+                _ => Ok(format!("object{{{}}}", fields.join(", "))),
+            }
+        },
+        Enum(ref v, ref p) => {
+            let p = p.borrow().clone();
+            match p.len() {
+                0 => Ok(format!("{}::{}", v.name, v.variant)),
+                _ => Ok(format!("{}::{}({})", v.name, v.variant, show_all(&p)?)),
+            }
+        },
+        Map(ref m) => {
+            let entries = m.borrow().values().cloned().collect::<Vec<_>>();
+            let entries = entries.iter().map(|(k,v)| Ok(format!("[{}]= {}", show(k, f)?, show(v, f)?))).collect::<Result<Vec<_>, Error>>()?;
+            Ok(format!("map{{{}}}", entries.join(", ")))
+        },
+        ref v => Ok(v.to_string()),
     }
 }
 
@@ -159,10 +229,50 @@ fn len(base_type: Value, args: &[Value]) -> ExprRes {
     match base_type {
         /*Str(ref s) => ,*/
         List(ref l) => Ok(Val(I(l.borrow().len() as i64))),
+        Range(s,e,st) => Ok(Val(I(range_len(s, e, st)))),
         _           => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
     }
 }
 
+fn first(base_type: Value, args: &[Value]) -> ExprRes {
+    use Value::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Pair(ref l, _)  => Ok(l.borrow().clone()),
+        _               => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
+fn second(base_type: Value, args: &[Value]) -> ExprRes {
+    use Value::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Pair(_, ref r)  => Ok(r.borrow().clone()),
+        _               => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
+fn swap(base_type: Value, args: &[Value]) -> ExprRes {
+    use Value::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Pair(ref l, ref r)  => Ok(Pair(Rc::new(RefCell::new(r.borrow().clone())), Rc::new(RefCell::new(l.borrow().clone())))),
+        _                   => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
 fn clone(base_type: Value, args: &[Value]) -> ExprRes {
     use Value::*;
     use self::VType::*;
@@ -175,8 +285,11 @@ fn clone(base_type: Value, args: &[Value]) -> ExprRes {
         Val(v)      => Ok(Val(v.clone())),
         Str(ref s)  => Ok(Str(Rc::new(RefCell::new(s.borrow().clone())))),
         List(ref l) => Ok(List(Rc::new(RefCell::new(l.borrow().clone())))),
-        Obj(ref o)  => Ok(Obj(Rc::new(RefCell::new(o.borrow().clone())))),
+        Obj(ref o, ref p) => Ok(Obj(Rc::new(RefCell::new(o.borrow().clone())), Rc::new(RefCell::new(p.borrow().clone())))),
+        Struct(ref n, ref o) => Ok(Struct(n.clone(), Rc::new(RefCell::new(o.borrow().clone())))),
+        Enum(ref v, ref p) => Ok(Enum(v.clone(), Rc::new(RefCell::new(p.borrow().clone())))),
         Map(ref m)  => Ok(Map(Rc::new(RefCell::new(m.borrow().clone())))),
+        Pair(ref l, ref r) => Ok(Pair(Rc::new(RefCell::new(l.borrow().clone())), Rc::new(RefCell::new(r.borrow().clone())))),
         Ref(ref r)  => match *r.borrow() {
             I(i)    => Ok(Val(I(i))),
             F(f)    => Ok(Val(F(f))),
@@ -322,8 +435,8 @@ fn is_field(base_type: Value, args: &[Value]) -> ExprRes {
     }
 
     match base_type {
-        Obj(ref o) => match args[0] {
-            Str(ref s) => Ok(Val(B(o.borrow().contains_key(&*s.borrow())))),
+        Obj(_,_) | Struct(_,_) => match args[0] {
+            Str(ref s) => Ok(Val(B(get_field(&base_type, &s.borrow()).is_some()))),
             _          => mserr(Type::RunTime(RunCode::CoreArgumentTypeError)),
         },
         _           => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
@@ -339,8 +452,8 @@ fn same(base_type: Value, args: &[Value]) -> ExprRes {
     }
 
     match base_type {
-        Obj(ref o) => match args[0] {
-            Obj(ref ob) => {
+        Obj(ref o, _) => match args[0] {
+            Obj(ref ob, _) => {
                 if o.borrow().len() != ob.borrow().len() {
                     return Ok(Val(B(false)));
                 }
@@ -357,6 +470,93 @@ fn same(base_type: Value, args: &[Value]) -> ExprRes {
     }
 }
 
+// Sets the object's prototype to another object, or removes it with null.
+fn set_proto(base_type: Value, args: &[Value]) -> ExprRes {
+    use Value::*;
+
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Obj(ref o, ref p) => match args[0] {
+            Obj(_,_) => {
+                // The object can't be in its own prototype chain.
+                let mut next = Some(args[0].clone());
+                while let Some(Obj(po, pp)) = next {
+                    if Rc::ptr_eq(o, &po) {
+                        return mserr(Type::RunTime(RunCode::CyclicPrototype));
+                    }
+                    next = pp.borrow().clone();
+                }
+
+                *p.borrow_mut() = Some(args[0].clone());
+                Ok(Null)
+            },
+            Null => {
+                *p.borrow_mut() = None;
+                Ok(Null)
+            },
+            _ => mserr(Type::RunTime(RunCode::CoreArgumentTypeError)),
+        },
+        _ => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
+fn get_proto(base_type: Value, args: &[Value]) -> ExprRes {
+    use Value::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Obj(_, ref p) => Ok(p.borrow().clone().unwrap_or(Null)),
+        _ => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
+fn to_list(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    use Value::*;
+    use self::VType::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let list = match base_type {
+        List(ref l) => l.borrow().clone(),
+        Range(s,e,st) => range_iter(s, e, st).map(|i| Val(I(i))).collect(),
+        Iter(ref i) => i.borrow_mut().by_ref().collect(),
+        Gen(ref g) => {
+            let mut list = Vec::new();
+            while let Resumed::Yielded(v) = Generator::resume(g, f)? {
+                list.push(v);
+            }
+            list
+        },
+        _ => return mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    };
+    Ok(List(Rc::new(RefCell::new(list))))
+}
+
+// Resumes a generator, giving the value it yields, or null once it has finished.
+fn next(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    use Value::*;
+
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    match base_type {
+        Gen(ref g) => match Generator::resume(g, f)? {
+            Resumed::Yielded(v) => Ok(v),
+            Resumed::Finished(_) => Ok(Null),
+        },
+        _ => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
+    }
+}
+
 fn insert(base_type: Value, args: &[Value]) -> ExprRes {
     use Value::*;
 
@@ -374,3 +574,214 @@ fn insert(base_type: Value, args: &[Value]) -> ExprRes {
         _           => mserr(Type::RunTime(RunCode::CoreBaseTypeError)),
     }
 }
+
+// Elements of a list, or values of a range, iterator or generator, gone through like a for
+// loop. A list is copied first so that the functions called on them can change it.
+fn list_items<'a>(base_type: &Value, f: &'a FuncMap) -> Result<impl Iterator<Item = Result<Value, Error>> + 'a, Error> {
+    let mut items = match *base_type {
+        Value::List(_) | Value::Range(_,_,_) | Value::Iter(_) | Value::Gen(_) => ForIter::new(base_type),
+        _ => None,
+    }.ok_or_else(|| Error::new(Type::RunTime(RunCode::CoreBaseTypeError)))?;
+
+    Ok(iter::from_fn(move || items.next(f)).map(|item| item.map(|(_, v)| v)))
+}
+
+// Elements of a list to sort, which has to be a list as it is sorted in place.
+fn sort_items(base_type: &Value) -> Result<Vec<Value>, Error> {
+    match *base_type {
+        Value::List(ref l) => Ok(l.borrow().clone()),
+        _ => Err(Error::new(Type::RunTime(RunCode::CoreBaseTypeError))),
+    }
+}
+
+// Calls a function or closure passed to a core function.
+fn call_arg(func: &Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    match *func {
+        Value::Func(_,_) | Value::Closure(_,_) => call_value(func.clone(), None, args, f),
+        _ => mserr(Type::RunTime(RunCode::CoreArgumentTypeError)),
+    }
+}
+
+// Calls a function that should give a bool.
+fn call_test(func: &Value, args: &[Value], f: &FuncMap) -> Result<bool, Error> {
+    truthy(&call_arg(func, args, f)?).ok_or_else(|| Error::new(Type::RunTime(RunCode::TypeError)))
+}
+
+fn map(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let list = list_items(&base_type, f)?
+        .map(|v| call_arg(&args[0], &[v?], f))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::List(Rc::new(RefCell::new(list))))
+}
+
+fn filter(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let mut list = Vec::new();
+    for v in list_items(&base_type, f)? {
+        let v = v?;
+        if call_test(&args[0], slice::from_ref(&v), f)? {
+            list.push(v);
+        }
+    }
+    Ok(Value::List(Rc::new(RefCell::new(list))))
+}
+
+// Combines the elements into one value, starting from the first argument and calling the
+// function with the value so far and each element in turn.
+fn reduce(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 2 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    list_items(&base_type, f)?
+        .try_fold(args[0].clone(), |acc, v| call_arg(&args[1], &[acc, v?], f))
+}
+
+// First element the function is true for, or null.
+fn find(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    for v in list_items(&base_type, f)? {
+        let v = v?;
+        if call_test(&args[0], slice::from_ref(&v), f)? {
+            return Ok(v);
+        }
+    }
+    Ok(Value::Null)
+}
+
+fn any(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    use self::VType::*;
+
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    for v in list_items(&base_type, f)? {
+        let v = v?;
+        if call_test(&args[0], slice::from_ref(&v), f)? {
+            return Ok(Value::Val(B(true)));
+        }
+    }
+    Ok(Value::Val(B(false)))
+}
+
+fn all(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    use self::VType::*;
+
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    for v in list_items(&base_type, f)? {
+        let v = v?;
+        if !call_test(&args[0], slice::from_ref(&v), f)? {
+            return Ok(Value::Val(B(false)));
+        }
+    }
+    Ok(Value::Val(B(true)))
+}
+
+fn for_each(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    for v in list_items(&base_type, f)? {
+        call_arg(&args[0], &[v?], f)?;
+    }
+    Ok(Value::Null)
+}
+
+// Stable merge sort. The comparison is script code, so it can fail, and isn't trusted to be
+// consistent.
+fn merge_sort<F>(mut list: Vec<Value>, less: &mut F) -> Result<Vec<Value>, Error>
+    where F: FnMut(&Value, &Value) -> Result<bool, Error>
+{
+    if list.len() < 2 {
+        return Ok(list);
+    }
+
+    let right = list.split_off(list.len() / 2);
+    let mut left = merge_sort(list, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+
+    let mut sorted = Vec::new();
+    loop {
+        let next = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) => if less(r, l)? {right.next()} else {left.next()},
+            (Some(_), None) => left.next(),
+            (None, Some(_)) => right.next(),
+            (None, None) => return Ok(sorted),
+        };
+        sorted.extend(next);
+    }
+}
+
+// Sorts the list in place with `<`.
+fn sort(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if !args.is_empty() {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let sorted = merge_sort(sort_items(&base_type)?, &mut |a, b| less_than(a, b, f))?;
+    if let Value::List(ref l) = base_type {
+        *l.borrow_mut() = sorted;
+    }
+    Ok(Value::Null)
+}
+
+// Sorts the list in place, with a function that says whether its first argument goes
+// before its second.
+fn sort_by(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let sorted = merge_sort(sort_items(&base_type)?, &mut |a, b| call_test(&args[0], &[a.clone(), b.clone()], f))?;
+    if let Value::List(ref l) = base_type {
+        *l.borrow_mut() = sorted;
+    }
+    Ok(Value::Null)
+}
+
+// Element that would be sorted first by `sort_by`, or null if the list is empty.
+fn min_by(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let mut min = Value::Null;
+    for (i, v) in list_items(&base_type, f)?.enumerate() {
+        let v = v?;
+        if i == 0 || call_test(&args[0], &[v.clone(), min.clone()], f)? {
+            min = v;
+        }
+    }
+    Ok(min)
+}
+
+// Element that would be sorted last by `sort_by`, or null if the list is empty.
+fn max_by(base_type: Value, args: &[Value], f: &FuncMap) -> ExprRes {
+    if args.len() != 1 {
+        return mserr(Type::RunTime(RunCode::CoreWrongNumberOfArguments));
+    }
+
+    let mut max = Value::Null;
+    for (i, v) in list_items(&base_type, f)?.enumerate() {
+        let v = v?;
+        if i == 0 || !call_test(&args[0], &[v.clone(), max.clone()], f)? {
+            max = v;
+        }
+    }
+    Ok(max)
+}
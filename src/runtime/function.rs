@@ -1,17 +1,61 @@
 use super::{Value, ExprRes};
-use error::{mserr, Type, RunCode};
+use ast::Called;
+use error::{mserr, Error, Type, RunCode};
 use std::collections::HashMap;
+use std::cell::RefCell;
 
-pub type PackageRoot = Box<dyn Fn(&str, &[Value], &FuncMap) -> ExprRes>;
+// Functions that can be attached to a function map under a package name. Any closure
+// `|name, args, funcs| -> ExprRes` is a package.
+pub trait Package {
+    fn call(&self, name: &str, args: &[Value], f: &FuncMap) -> ExprRes;
+
+    // Calls a function as a method of the object. Packages that don't bind `self` call it
+    // like any other function.
+    fn call_method(&self, name: &str, _this: &Value, args: &[Value], f: &FuncMap) -> ExprRes {
+        self.call(name, args, f)
+    }
+
+    // Calls a function, or a method if given the object, so that a yield inside it suspends
+    // the call rather than failing. Packages that can't be suspended just call it.
+    fn call_suspendable(&self, name: &str, this: Option<&Value>, args: &[Value], f: &FuncMap) -> Result<Called, Error> {
+        let res = match this {
+            Some(this) => self.call_method(name, this, args, f),
+            None => self.call(name, args, f),
+        };
+        res.map(Called::Returned)
+    }
+
+    // Builds an instance of a struct declared in the package from an object of its fields.
+    // Only script packages declare structs.
+    fn construct_struct(&self, _name: &str, _fields: &Value, _f: &FuncMap) -> ExprRes {
+        mserr(Type::RunTime(RunCode::StructNotFound))
+    }
+
+    // Builds an instance of a variant of an enum declared in the package, holding the payload.
+    fn construct_variant(&self, _name: &str, _variant: &str, _args: &[Value]) -> ExprRes {
+        mserr(Type::RunTime(RunCode::EnumNotFound))
+    }
+}
+
+impl<F: Fn(&str, &[Value], &FuncMap) -> ExprRes> Package for F {
+    fn call(&self, name: &str, args: &[Value], f: &FuncMap) -> ExprRes {
+        self(name, args, f)
+    }
+}
+
+pub type PackageRoot = Box<dyn Package>;
 
 pub struct FuncMap {
     packages: HashMap<String, PackageRoot>,
+    // Value a native function asked the script to yield, during the package call it was made in
+    yield_request: RefCell<Option<Value>>,
 }
 
 impl FuncMap {
     pub fn new() -> Self {
         FuncMap {
             packages: HashMap::new(),
+            yield_request: RefCell::new(None),
         }
     }
 
@@ -20,9 +64,65 @@ impl FuncMap {
     }
 
     pub fn call_fn(&self, package: &str, name: &str, args: &[Value]) -> ExprRes {
+        self.call_unsuspended(package, |p| p.call(name, args, self))
+    }
+
+    // Calls a function as a method of the object. Script packages bind the object to `self`.
+    pub fn call_method(&self, package: &str, name: &str, this: &Value, args: &[Value]) -> ExprRes {
+        self.call_unsuspended(package, |p| p.call_method(name, this, args, self))
+    }
+
+    // Builds an instance of a struct declared in the package, for a struct literal.
+    pub fn construct_struct(&self, package: &str, name: &str, fields: &Value) -> ExprRes {
+        self.call_unsuspended(package, |p| p.construct_struct(name, fields, self))
+    }
+
+    // Builds an instance of an enum variant declared in the package.
+    pub fn construct_variant(&self, package: &str, name: &str, variant: &str, args: &[Value]) -> ExprRes {
+        self.call_unsuspended(package, |p| p.construct_variant(name, variant, args))
+    }
+
+    // Calls a function, or a method if given the object, so that a yield inside a script
+    // function suspends the call rather than failing, and a yield request is passed on.
+    pub fn call_suspendable(&self, package: &str, name: &str, this: Option<&Value>, args: &[Value]) -> Result<Called, Error> {
+        match self.call_package(package, |p| p.call_suspendable(name, this, args, self))? {
+            (called, None) => Ok(called),
+            (Called::Returned(v), Some(y)) => Ok(Called::Requested(v, y)),
+            (_, Some(_)) => Err(Error::new(Type::RunTime(RunCode::CannotYield))),
+        }
+    }
+
+    // A yield request can't be honoured by a call that isn't suspendable, so it fails.
+    fn call_unsuspended<C: FnOnce(&PackageRoot) -> ExprRes>(&self, package: &str, call: C) -> ExprRes {
+        match self.call_package(package, call)? {
+            (v, None) => Ok(v),
+            (_, Some(_)) => mserr(Type::RunTime(RunCode::CannotYield)),
+        }
+    }
+
+    // Calls into the package, along with any yield request made by it.
+    fn call_package<T, C: FnOnce(&PackageRoot) -> Result<T, Error>>(&self, package: &str, call: C) -> Result<(T, Option<Value>), Error> {
         match self.packages.get(package) {
-            Some(p) => p(name, args, self),
-            None => mserr(Type::RunTime(RunCode::PackageNotFound)),
+            Some(p) => {
+                let (res, request) = self.without_yield_request(|| call(p));
+                res.map(|v| (v, request))
+            },
+            None => Err(Error::new(Type::RunTime(RunCode::PackageNotFound))),
         }
     }
+
+    // Asks the script to yield the value once the statement calling the native function is
+    // done. It is resumed by whatever is running it, a coroutine or a generator. A call that
+    // isn't a whole statement can't be suspended, so it fails with `CannotYield` instead.
+    pub fn request_yield(&self, val: Value) {
+        self.yield_request.replace(Some(val));
+    }
+
+    // Runs the call with no yield request pending, giving back any request made during it.
+    // A request made before the call is kept for after it.
+    pub fn without_yield_request<T, C: FnOnce() -> T>(&self, call: C) -> (T, Option<Value>) {
+        let outer = self.yield_request.replace(None);
+        let res = call();
+        (res, self.yield_request.replace(outer))
+    }
 }
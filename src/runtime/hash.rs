@@ -27,6 +27,13 @@ pub fn hash_value(val: &Value) -> Result<HashV, Error> {
                 internal_hash_value(&r.borrow(), state)
             },
             Str(ref s)  => Ok(s.borrow().hash(state)),
+            Enum(ref v, ref p) => {
+                v.hash(state);
+                for x in p.borrow().iter() {
+                    internal_hash_value(x, state)?;
+                }
+                Ok(())
+            },
             _           => Err(Error::new(Type::RunTime(RunCode::ValueNotHashable))),
         }
     }
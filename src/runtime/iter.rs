@@ -0,0 +1,72 @@
+// Lazy sequences of values
+use super::{Value, VType};
+
+use std::fmt;
+use std::cmp;
+use std::iter;
+
+// Values produced one at a time by a rust iterator. Going through it uses it up.
+pub struct NativeIter {
+    iter: Box<dyn Iterator<Item = Value>>,
+}
+
+impl NativeIter {
+    pub fn new<I: Iterator<Item = Value> + 'static>(i: I) -> Self {
+        NativeIter {
+            iter: Box::new(i),
+        }
+    }
+}
+
+impl Iterator for NativeIter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.iter.next()
+    }
+}
+
+impl cmp::PartialEq for NativeIter {
+    fn eq(&self, _: &NativeIter) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for NativeIter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iterator")
+    }
+}
+
+// The ints of a range, going from the start towards the end (which isn't included) in steps.
+pub fn range_iter(start: i64, end: i64, step: i64) -> impl Iterator<Item = i64> {
+    iter::successors(Some(start), move |i| i.checked_add(step))
+        .take_while(move |&i| if step > 0 {i < end} else {i > end})
+}
+
+// Number of ints in a range.
+pub fn range_len(start: i64, end: i64, step: i64) -> i64 {
+    let dist = if step > 0 {end as i128 - start as i128} else {start as i128 - end as i128};
+    if dist <= 0 {
+        0
+    } else {
+        ((dist - 1) / (step as i128).abs() + 1) as i64
+    }
+}
+
+// Int at a position in a range, counting back from the end for negative positions like a list.
+pub fn range_get(start: i64, end: i64, step: i64, i: i64) -> Option<i64> {
+    let len = range_len(start, end, step);
+    let i = if i < 0 {len.checked_add(i)?} else {i};
+
+    if (i >= 0) && (i < len) {
+        Some((start as i128 + i as i128 * step as i128) as i64)
+    } else {
+        None
+    }
+}
+
+// The ints of a range as values.
+pub fn range_values(start: i64, end: i64, step: i64) -> Vec<Value> {
+    range_iter(start, end, step).map(|i| Value::Val(VType::I(i))).collect()
+}
@@ -4,24 +4,34 @@ mod function;
 mod core;
 mod callable;
 mod hash;
+mod iter;
 
 pub use self::scope::*;
 pub use self::function::*;
 pub use self::core::core_func_call;
 pub use self::callable::*;
 pub use self::hash::*;
+pub use self::iter::*;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashMap;
 
-use error::Error;
-use ast::FuncRoot;
+use error::{Error, Span};
+use ast::{FuncRoot, Generator};
 
 use std::fmt;
 
 pub type Ref<T> = Rc<RefCell<T>>;
 
+// Variant of an enum, along with the enum and the package it is declared in.
+#[derive(PartialEq, Hash, Debug)]
+pub struct Variant {
+    pub package: String,
+    pub name: String,
+    pub variant: String,
+}
+
 // Types
 #[derive(Clone, PartialEq, Debug)]
 pub enum Value {
@@ -37,9 +47,17 @@ pub enum Value {
     // Reference types
     Str(Ref< String >),
     List(Ref< Vec<Value> >),
-    Obj(Ref< HashMap<String,Value> >),
+    // Objects have an optional prototype, which is searched for fields they don't have
+    Obj(Ref< HashMap<String,Value> >, Ref< Option<Value> >),
+    Struct(Rc<str>, Ref< HashMap<String,Value> >),
+    Enum(Rc<Variant>, Ref< Vec<Value> >),
     Map(Ref< HashMap<HashV,(Value,Value)> >),
 
+    // Lazy sequences: ints from a start towards an end in steps, and values from a rust iterator
+    Range(i64, i64, i64),
+    Iter(Ref< NativeIter >),
+    Gen(Ref< Generator >),
+
     // Callable reference types
     Func(Ref< String >, Ref< String >),
     Closure(Ref< FuncRoot >, Ref< Vec<(String,Value)> >),
@@ -56,6 +74,7 @@ pub enum VType {
     B(bool),
 }
 
+// Display can't call script functions, so it ignores `__str` hooks: `to_string` goes through them.
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::Value::*;
@@ -82,7 +101,7 @@ impl fmt::Display for Value {
                 }
                 write!(f, "]")
             },
-            Obj(ref o) => {
+            Obj(ref o, _) => {
                 let o = o.borrow();
                 write!(f, "object{{")?;
                 if o.len() > 0 {
@@ -94,6 +113,21 @@ impl fmt::Display for Value {
                 }
                 write!(f, "}}")
             },
+            Struct(ref n, ref o) => {
+                let o = o.borrow();
+                write!(f, "{}{{", n)?;
+                let fields = o.iter().map(|(k,v)| format!("{}: {}", k, v)).collect::<Vec<_>>();
+                write!(f, "{}}}", fields.join(", "))
+            },
+            Enum(ref v, ref p) => {
+                let p = p.borrow();
+                write!(f, "{}::{}", v.name, v.variant)?;
+                if !p.is_empty() {
+                    let payload = p.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                    write!(f, "({})", payload.join(", "))?;
+                }
+                Ok(())
+            },
             Map(ref m) => {
                 let m = m.borrow();
                 write!(f, "map{{")?;
@@ -106,6 +140,16 @@ impl fmt::Display for Value {
                 }
                 write!(f, "}}")
             },
+            Range(start, end, step) => {
+                let default = if start <= end {1} else {-1};
+                if step == default {
+                    write!(f, "[{}..{}]", start, end)
+                } else {
+                    write!(f, "[{}..{}..{}]", start, step, end)
+                }
+            },
+            Iter(_) => write!(f, "iterator"),
+            Gen(_) => write!(f, "generator"),
             Func(ref p, ref n) => {
                 let p = p.borrow();
                 let n = n.borrow();
@@ -159,7 +203,7 @@ pub fn equal(l: &Value, r: &Value) -> Option<bool> {
             }
             Some(true)
         },
-        (Obj(x),Obj(y)) => {
+        (Obj(x,_),Obj(y,_)) => {
             if x.borrow().len() != y.borrow().len() {
                 return Some(false);
             }
@@ -170,6 +214,39 @@ pub fn equal(l: &Value, r: &Value) -> Option<bool> {
             }
             Some(true)
         },
+        (Struct(n,x),Struct(m,y)) => {
+            if n != m || x.borrow().len() != y.borrow().len() {
+                return Some(false);
+            }
+            let y = y.borrow();
+            Some(x.borrow().iter().all(|(k,v)| match (v, y.get(k)) {
+                // Methods are closures made from the same declaration in every instance.
+                (Closure(f,_), Some(Closure(g,_))) => Rc::ptr_eq(f, g),
+                (v, Some(w)) => equal(v, w) == Some(true),
+                (_, None) => false,
+            }))
+        },
+        (Enum(v,x),Enum(w,y)) => {
+            if v != w || x.borrow().len() != y.borrow().len() {
+                return Some(false);
+            }
+            Some(x.borrow().iter().zip(y.borrow().iter()).all(|(a,b)| equal(a, b) == Some(true)))
+        },
+        (Pair(a,b),Pair(c,d)) => {
+            let first = equal(&a.borrow(), &c.borrow()) == Some(true);
+            Some(first && equal(&b.borrow(), &d.borrow()) == Some(true))
+        },
+        (Range(a,b,c),Range(x,y,z)) => Some((a,b,c) == (x,y,z)),
+        // A range is equal to a list of the same ints.
+        (Range(a,b,c),List(l)) | (List(l),Range(a,b,c)) => {
+            let l = l.borrow();
+            if l.len() as i64 != range_len(*a, *b, *c) {
+                return Some(false);
+            }
+            Some(l.iter().zip(range_iter(*a, *b, *c)).all(|(v,i)| equal(v, &Val(VType::I(i))) == Some(true)))
+        },
+        (Iter(x),Iter(y)) => Some(Rc::ptr_eq(x, y)),
+        (Gen(x),Gen(y)) => Some(Rc::ptr_eq(x, y)),
         (Null,Null) => Some(true),
         // map
         // Null?
@@ -177,14 +254,76 @@ pub fn equal(l: &Value, r: &Value) -> Option<bool> {
     }
 }
 
+// Field of an object or struct. Objects without the field look for it in their prototypes.
+pub fn get_field(v: &Value, name: &str) -> Option<Value> {
+    match *v {
+        Value::Obj(ref o, ref proto) => match o.borrow().get(name) {
+            Some(f) => Some(f.clone()),
+            None => proto.borrow().as_ref().and_then(|p| get_field(p, name)),
+        },
+        Value::Struct(_, ref o) => o.borrow().get(name).cloned(),
+        _ => None,
+    }
+}
+
+// Truth value of a condition: bools, or ints which are true when non-zero.
+pub fn truthy(v: &Value) -> Option<bool> {
+    match *v {
+        Value::Val(VType::B(b)) => Some(b),
+        Value::Val(VType::I(i)) => Some(i != 0),
+        _ => None,
+    }
+}
+
+pub const TYPE_NAMES: &[&str] = &["int", "float", "bool", "pair", "string", "list", "object", "enum", "map", "range", "iterator", "generator", "func", "null"];
+
+// Name of the value's type, as used by type test patterns.
+pub fn type_name(v: &Value) -> &'static str {
+    use self::Value::*;
+    use self::VType::*;
+
+    match *v {
+        Val(I(_)) => "int",
+        Val(F(_)) => "float",
+        Val(B(_)) => "bool",
+        Ref(ref r) => match *r.borrow() {
+            I(_) => "int",
+            F(_) => "float",
+            B(_) => "bool",
+        },
+        Pair(_,_) => "pair",
+        Str(_) => "string",
+        List(_) => "list",
+        Obj(_,_) | Struct(_,_) => "object",
+        Enum(_,_) => "enum",
+        Map(_) => "map",
+        Range(_,_,_) => "range",
+        Iter(_) => "iterator",
+        Gen(_) => "generator",
+        Func(_,_) | Closure(_,_) => "func",
+        Null => "null",
+    }
+}
+
 // Runtime Signals
 pub enum Signal {
     Error(Error),
     Return(Value),
     Continue,
     Break,
-    // Exception,
+    Throw(Value),
+    Yield(Value),
     Done,
 }
 
+impl Signal {
+    // Sets the location of an error signal, if it isn't already known.
+    pub fn at(self, span: &Span) -> Self {
+        match self {
+            Signal::Error(e) => Signal::Error(e.at(span)),
+            s => s,
+        }
+    }
+}
+
 pub type ExprRes = Result<Value, Error>;
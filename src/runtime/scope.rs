@@ -1,4 +1,5 @@
 use super::{Value, Signal, ExprRes};
+use ast::Resume;
 use error::{mserr, Error, Type, RunCode, CriticalCode};
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -6,6 +7,8 @@ use std::cell::RefCell;
 
 pub struct Scope {
     vars: Vec<HashMap<String, Value>>,
+    // Where the statements were when a yield suspended them, from the innermost out
+    resume: Vec<Resume>,
 }
 
 
@@ -13,9 +16,20 @@ impl Scope {
     pub fn new() -> Self {
         Scope {
             vars: vec![HashMap::new()],
+            resume: Vec::new(),
         }
     }
 
+    pub fn push_resume(&mut self, r: Resume) {
+        self.resume.push(r);
+    }
+
+    // Takes the resume point of the outermost statement not yet resumed. Only the statements
+    // that were suspended run while resuming, so it belongs to the statement that calls this.
+    pub fn pop_resume(&mut self) -> Option<Resume> {
+        self.resume.pop()
+    }
+
     pub fn extend(&mut self) {
         self.vars.push(HashMap::new());
     }